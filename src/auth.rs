@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::header::COOKIE;
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{DjangoSession, DjangoUser, Profile};
+
+/// Django's default `SESSION_COOKIE_NAME`.
+const SESSION_COOKIE: &str = "sessionid";
+/// `key_salt` of Django's `AbstractBaseUser.get_session_auth_hash`.
+const SESSION_HASH_SALT: &str = "django.contrib.auth.models.AbstractBaseUser.get_session_auth_hash";
+
+/// The profile of the user logged in through Django, or `None` for anonymous visitors.
+pub struct CurrentUser(pub Option<Profile>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    DbPool: FromRef<S>,
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = DbPool::from_ref(state);
+        let config = Arc::<Config>::from_ref(state);
+        Ok(CurrentUser(current_user(&pool, &config, &parts.headers).await?))
+    }
+}
+
+/// Looks up the profile behind the request's Django session cookie, if any.
+///
+/// Like Django, the session only counts while the user is active and its auth hash still
+/// matches the password, so deactivating a user or changing the password logs them out.
+/// Without `DJANGO_SECRET_KEY` the hash can't be checked, and admin sessions are ignored.
+pub async fn current_user(pool: &DbPool, config: &Config, headers: &HeaderMap) -> Result<Option<Profile>, AppError> {
+    let Some(session_key) = session_cookie(headers) else {
+        return Ok(None);
+    };
+    let Some(session) = DjangoSession::find_active(pool, &session_key).await? else {
+        return Ok(None);
+    };
+    let Some(user_id) = session.user_id() else {
+        return Ok(None);
+    };
+    let Some(user) = DjangoUser::find_by_id(pool, user_id).await?.filter(|u| u.is_active) else {
+        return Ok(None);
+    };
+
+    let hash_checked = match config.django_secret_key.as_deref() {
+        Some(secret) => {
+            let hash = session.auth_user_hash().unwrap_or_default();
+            if !session_hash_matches(secret, &user.password, &hash) {
+                return Ok(None);
+            }
+            true
+        }
+        None => false,
+    };
+
+    let profile = Profile::find_by_user_id(pool, user.id).await?;
+    Ok(profile.filter(|profile| hash_checked || !profile.is_admin()))
+}
+
+/// Django's `get_session_auth_hash`: `salted_hmac` (SHA-256) of the password hash.
+fn session_auth_hash(secret: &str, password: &str) -> Vec<u8> {
+    let key = Sha256::digest(format!("{}{}", SESSION_HASH_SALT, secret));
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts keys of any length");
+    mac.update(password.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Compares the hex hash stored in the session in constant time.
+fn session_hash_matches(secret: &str, password: &str, session_hash: &str) -> bool {
+    let expected = session_auth_hash(secret, password);
+    let hex: String = expected.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.len() == session_hash.len()
+        && hex.bytes().zip(session_hash.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "django-insecure-test-key";
+    const PASSWORD: &str = "pbkdf2_sha256$600000$salt$hash=";
+    /// `user.get_session_auth_hash()` from Django for `PASSWORD` under `SECRET`
+    const DJANGO_HASH: &str = "566fde8a582c63be58a059e10034d622326462ed2ad1d0785e335ff997e8f9f2";
+
+    #[test]
+    fn session_hash_matches_django() {
+        assert!(session_hash_matches(SECRET, PASSWORD, DJANGO_HASH));
+    }
+
+    #[test]
+    fn changed_password_or_secret_invalidates_the_session() {
+        assert!(!session_hash_matches(SECRET, "pbkdf2_sha256$600000$salt$new=", DJANGO_HASH));
+        assert!(!session_hash_matches("another-key", PASSWORD, DJANGO_HASH));
+        assert!(!session_hash_matches(SECRET, PASSWORD, ""));
+        assert!(!session_hash_matches(SECRET, PASSWORD, &DJANGO_HASH[..40]));
+        assert!(!session_hash_matches(SECRET, PASSWORD, &DJANGO_HASH.to_uppercase()));
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::LazyLock;
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+use crate::meta::SITE_NAME;
+
+/// Card size recommended for OpenGraph and Twitter's large image card.
+pub const WIDTH: u32 = 1200;
+pub const HEIGHT: u32 = 630;
+
+static REGULAR: LazyLock<FontRef<'static>> = LazyLock::new(|| {
+    FontRef::try_from_slice(include_bytes!("../assets/fonts/DejaVuSans.ttf")).expect("embedded font is valid")
+});
+static BOLD: LazyLock<FontRef<'static>> = LazyLock::new(|| {
+    FontRef::try_from_slice(include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf")).expect("embedded font is valid")
+});
+
+type Rgb = [u8; 3];
+
+// Bootstrap's dark, primary and muted colors, matching the site
+const BACKGROUND: Rgb = [0x21, 0x25, 0x29];
+const ACCENT: Rgb = [0x0d, 0x6e, 0xfd];
+const TAG: Rgb = [0x6e, 0xa8, 0xfe];
+const TEXT: Rgb = [0xff, 0xff, 0xff];
+const MUTED: Rgb = [0xad, 0xb5, 0xbd];
+
+const MARGIN: f32 = 80.0;
+const LINE_HEIGHT: f32 = 1.25;
+
+/// Renders a share card as a PNG: site name, headline, an optional summary below it and
+/// the tags along the bottom. Text that doesn't fit is cut with an ellipsis.
+pub fn render(headline: &str, summary: Option<&str>, tags: &[String]) -> Result<Vec<u8>, png::EncodingError> {
+    let mut canvas = Canvas::new(BACKGROUND);
+    canvas.fill_rect(0, 0, 16, HEIGHT, ACCENT);
+    let max_width = WIDTH as f32 - 2.0 * MARGIN;
+
+    canvas.draw_text(&BOLD, 30.0, MARGIN, MARGIN - 20.0, SITE_NAME, MUTED);
+
+    // Untitled posts use their (longer) preview as the headline, so give it more room
+    let (size, max_lines) = if summary.is_some() { (64.0, 3) } else { (48.0, 5) };
+    let mut y = MARGIN + 60.0;
+    for line in wrap(&BOLD, size, headline, max_width, max_lines) {
+        canvas.draw_text(&BOLD, size, MARGIN, y, &line, TEXT);
+        y += size * LINE_HEIGHT;
+    }
+
+    if let Some(summary) = summary {
+        let size = 30.0;
+        let room = ((HEIGHT as f32 - MARGIN - 60.0 - y) / (size * LINE_HEIGHT)) as usize;
+        y += 16.0;
+        for line in wrap(&REGULAR, size, summary, max_width, room.min(3)) {
+            canvas.draw_text(&REGULAR, size, MARGIN, y, &line, MUTED);
+            y += size * LINE_HEIGHT;
+        }
+    }
+
+    if !tags.is_empty() {
+        let line = tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join("   ");
+        let line = truncate(&BOLD, 28.0, &line, max_width, false);
+        canvas.draw_text(&BOLD, 28.0, MARGIN, HEIGHT as f32 - MARGIN - 28.0, &line, TAG);
+    }
+
+    canvas.encode()
+}
+
+/// RGB pixel buffer the card is drawn into.
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(background: Rgb) -> Self {
+        Canvas { pixels: background.repeat((WIDTH * HEIGHT) as usize) }
+    }
+
+    fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb) {
+        for row in y..(y + height).min(HEIGHT) {
+            for col in x..(x + width).min(WIDTH) {
+                self.blend(col as i32, row as i32, color, 1.0);
+            }
+        }
+    }
+
+    /// Draws a single line of text with its top edge at `top`.
+    fn draw_text(&mut self, font: &FontRef<'static>, size: f32, x: f32, top: f32, text: &str, color: Rgb) {
+        let scaled = font.as_scaled(PxScale::from(size));
+        let baseline = top + scaled.ascent();
+        let mut caret = x;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(size, point(caret, baseline));
+            caret += scaled.h_advance(id);
+            previous = Some(id);
+
+            if let Some(outline) = font.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    self.blend(bounds.min.x as i32 + gx as i32, bounds.min.y as i32 + gy as i32, color, coverage);
+                });
+            }
+        }
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: Rgb, alpha: f32) {
+        if x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 {
+            return;
+        }
+        let offset = (y as usize * WIDTH as usize + x as usize) * 3;
+        let alpha = alpha.clamp(0.0, 1.0);
+        for (pixel, channel) in self.pixels[offset..offset + 3].iter_mut().zip(color) {
+            *pixel = (*pixel as f32 * (1.0 - alpha) + channel as f32 * alpha).round() as u8;
+        }
+    }
+
+    fn encode(self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, WIDTH, HEIGHT);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(png)
+    }
+}
+
+fn measure(font: &FontRef<'static>, size: f32, text: &str) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+/// Greedy word wrap to at most `max_lines` lines, ending with an ellipsis when text is left over.
+fn wrap(font: &FontRef<'static>, size: f32, text: &str, max_width: f32, max_lines: usize) -> Vec<String> {
+    if max_lines == 0 {
+        return vec![];
+    }
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut overflow = false;
+    for word in text.split_whitespace() {
+        if line.is_empty() {
+            line.push_str(word);
+            continue;
+        }
+        let candidate = format!("{} {}", line, word);
+        if measure(font, size, &candidate) <= max_width {
+            line = candidate;
+        } else if lines.len() + 1 == max_lines {
+            overflow = true;
+            break;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    // Single words wider than a line get cut too
+    let last = lines.len().saturating_sub(1);
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| truncate(font, size, line, max_width, overflow && i == last))
+        .collect()
+}
+
+/// Cuts `text` to `max_width`, adding an ellipsis if anything was removed (or `force` is set).
+fn truncate(font: &FontRef<'static>, size: f32, text: &str, max_width: f32, force: bool) -> String {
+    if !force && measure(font, size, text) <= max_width {
+        return text.to_string();
+    }
+    let mut text = text.trim_end_matches(['.', ' ']).to_string();
+    while !text.is_empty() && measure(font, size, &format!("{}…", text)) > max_width {
+        text.pop();
+    }
+    format!("{}…", text.trim_end())
+}
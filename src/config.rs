@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Directory holding Django's uploaded media, used when no proxy serves the files.
+    pub media_root: PathBuf,
+    /// Key for signing share links; share links are disabled when unset.
+    pub share_link_secret: Option<String>,
+    /// Django's `SECRET_KEY`, to check that sessions still match the user's password.
+    /// Without it, admin sessions are treated as anonymous.
+    pub django_secret_key: Option<String>,
+    /// Public origin without a trailing slash, for absolute URLs in feeds.
+    pub site_url: String,
+    /// Where generated share cards are cached.
+    pub card_cache_dir: PathBuf,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            media_root: std::env::var("MEDIA_ROOT")
+                .unwrap_or_else(|_| "/var/www/media".into())
+                .into(),
+            share_link_secret: std::env::var("SHARE_LINK_SECRET").ok().filter(|s| !s.is_empty()),
+            django_secret_key: std::env::var("DJANGO_SECRET_KEY").ok().filter(|s| !s.is_empty()),
+            site_url: std::env::var("SITE_URL")
+                .unwrap_or_else(|_| "https://nestorwheelock.com".into())
+                .trim_end_matches('/')
+                .to_string(),
+            card_cache_dir: std::env::var("CARD_CACHE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| std::env::temp_dir().join("nestorwheelock-cards")),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 
@@ -9,3 +11,40 @@ pub async fn create_pool(database_url: &str) -> Result<DbPool, sqlx::Error> {
         .connect(database_url)
         .await
 }
+
+/// Whether `table` exists in the current schema, for tables Django may not have created.
+pub async fn table_exists(pool: &PgPool, table: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (
+             SELECT 1 FROM information_schema.tables
+             WHERE table_schema = current_schema() AND table_name = $1
+         )"
+    )
+    .bind(table)
+    .fetch_one(pool)
+    .await
+}
+
+/// `table_exists` for an optional table, remembered once the table is found. A missing table
+/// is checked again on every call, so creating it takes effect without a restart.
+pub struct TableProbe {
+    table: &'static str,
+    found: AtomicBool,
+}
+
+impl TableProbe {
+    pub const fn new(table: &'static str) -> Self {
+        TableProbe { table, found: AtomicBool::new(false) }
+    }
+
+    pub async fn exists(&self, pool: &PgPool) -> Result<bool, sqlx::Error> {
+        if self.found.load(Ordering::Relaxed) {
+            return Ok(true);
+        }
+        let exists = table_exists(pool, self.table).await?;
+        if exists {
+            self.found.store(true, Ordering::Relaxed);
+        }
+        Ok(exists)
+    }
+}
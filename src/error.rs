@@ -12,20 +12,34 @@ pub enum AppError {
     Internal(String),
 }
 
+/// Status and visitor-safe message of an error response, left in the response extensions
+/// so `handlers::errors::render_error_page` can turn the plain-text body into an HTML
+/// page or JSON.
+#[derive(Debug, Clone)]
+pub struct ErrorInfo {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+/// Shown instead of database and internal error details, which only go to the log.
+const INTERNAL_ERROR_MESSAGE: &str = "Something went wrong on our end";
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, message) = match self {
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg),
             AppError::Database(e) => {
                 tracing::error!("Database error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string())
+                (StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_ERROR_MESSAGE.to_string())
             }
             AppError::Internal(msg) => {
                 tracing::error!("Internal error: {}", msg);
-                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+                (StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_ERROR_MESSAGE.to_string())
             }
         };
-        (status, message).into_response()
+        let mut response = (status, message.clone()).into_response();
+        response.extensions_mut().insert(ErrorInfo { status, message });
+        response
     }
 }
 
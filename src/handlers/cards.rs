@@ -0,0 +1,101 @@
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+
+use crate::auth::CurrentUser;
+use crate::cards;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::handlers::posts::DetailParams;
+use crate::models::{Post, Tag};
+use crate::privacy::Viewer;
+use crate::share;
+
+/// Share card for a post, rendered on first request and kept on disk until the post is edited.
+pub async fn post_card(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    Query(params): Query<DetailParams>,
+) -> Result<Response> {
+    let mut viewer = Viewer::new(user.as_ref());
+    let post = Post::find_by_id(&pool, id).await?
+        .ok_or_else(|| AppError::NotFound("Post not found".to_string()))?;
+    if let Some(token) = params.share {
+        if share::resolve(&pool, &config, &token).await?.is_some_and(|share| share.post_id == post.id) {
+            viewer = viewer.with_share(post.id);
+        }
+    }
+    // The card shows the title and preview, so it's exactly as private as the post
+    viewer.require_post(&pool, &post).await?;
+
+    let file = cache_file(&config, &post);
+    let png = match tokio::fs::read(&file).await {
+        Ok(png) => png,
+        Err(_) => {
+            let png = render(&pool, &post).await?;
+            if let Err(e) = store(&config.card_cache_dir, post.id, &file, &png).await {
+                tracing::warn!("Failed to cache share card {}: {}", file.display(), e);
+            }
+            png
+        }
+    };
+
+    let cache_control = if user.is_some() || viewer.shared_post_id.is_some() {
+        "private, max-age=3600"
+    } else {
+        "public, max-age=86400"
+    };
+    Ok(([(CONTENT_TYPE, "image/png"), (CACHE_CONTROL, cache_control)], png).into_response())
+}
+
+/// Absolute card URL for `og:image`; the version changes whenever the post is edited.
+pub fn card_url(config: &Config, post: &Post) -> String {
+    format!("{}/posts/{}/card.png?v={}", config.site_url, post.id, post.updated_at.timestamp())
+}
+
+fn cache_file(config: &Config, post: &Post) -> PathBuf {
+    config.card_cache_dir.join(format!("{}-{}.png", post.id, post.updated_at.timestamp_micros()))
+}
+
+async fn render(pool: &DbPool, post: &Post) -> Result<Vec<u8>> {
+    let tags: Vec<String> = Tag::list_for_post(pool, post.id).await?
+        .into_iter()
+        .map(|tag| tag.name)
+        .collect();
+    let preview = post.preview();
+    let (headline, summary) = match post.title.clone().filter(|t| !t.trim().is_empty()) {
+        Some(title) => (title, Some(preview).filter(|p| !p.is_empty())),
+        None => (preview, None),
+    };
+
+    tokio::task::spawn_blocking(move || cards::render(&headline, summary.as_deref(), &tags))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Writes the card atomically and removes cards rendered for older versions of the post.
+async fn store(dir: &FsPath, post_id: i64, file: &FsPath, png: &[u8]) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    let temp = file.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    tokio::fs::write(&temp, png).await?;
+    tokio::fs::rename(&temp, file).await?;
+
+    let prefix = format!("{}-", post_id);
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let stale = entry.file_name().to_str()
+            .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".png"));
+        if stale && path != file {
+            tokio::fs::remove_file(path).await.ok();
+        }
+    }
+    Ok(())
+}
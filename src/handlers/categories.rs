@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::State;
+
+use crate::auth::CurrentUser;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::Result;
+use crate::models::{Category, NavPage, Page, Post, Profile};
+use crate::meta::PageMeta;
+use crate::privacy::Viewer;
+
+/// A row of the category tree, listed depth-first.
+pub struct CategoryEntry {
+    pub category: Category,
+    pub depth: usize,
+    /// Visible posts in the category and all of its descendants
+    pub post_count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "categories.html")]
+pub struct CategoriesTemplate {
+    pub entries: Vec<CategoryEntry>,
+    pub nav_pages: Vec<NavPage>,
+    pub current_path: String,
+    pub meta: PageMeta,
+    pub user: Option<Profile>,
+}
+
+pub async fn category_index(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+) -> Result<impl IntoResponse> {
+    let viewer = Viewer::new(user.as_ref());
+    let categories = Category::list_all(&pool).await?;
+    let counts = Post::count_by_category(&pool, &viewer).await?;
+    let nav_pages = Page::list_nav_pages(&pool).await?;
+
+    Ok(CategoriesTemplate {
+        entries: category_tree(categories, &counts),
+        nav_pages,
+        current_path: "/categories/".to_string(),
+        meta: PageMeta::new(&config, "/categories/", "Categories", "Posts by category"),
+        user,
+    })
+}
+
+/// Orders categories depth-first under their parents, keeping `display_order` among
+/// siblings. Categories whose parent is inactive are shown at the top level.
+fn category_tree(categories: Vec<Category>, counts: &HashMap<i64, i64>) -> Vec<CategoryEntry> {
+    let ids: Vec<i64> = categories.iter().map(|c| c.id).collect();
+    let mut children: HashMap<Option<i64>, Vec<Category>> = HashMap::new();
+    for category in categories {
+        let parent = category.parent_id.filter(|id| ids.contains(id));
+        children.entry(parent).or_default().push(category);
+    }
+
+    let mut entries = Vec::new();
+    add_subtree(None, 0, &mut children, counts, &mut entries);
+    entries
+}
+
+/// Appends the children of `parent` and their subtrees, returning their total post count.
+fn add_subtree(
+    parent: Option<i64>,
+    depth: usize,
+    children: &mut HashMap<Option<i64>, Vec<Category>>,
+    counts: &HashMap<i64, i64>,
+    entries: &mut Vec<CategoryEntry>,
+) -> i64 {
+    let mut total = 0;
+    for category in children.remove(&parent).unwrap_or_default() {
+        let index = entries.len();
+        let id = category.id;
+        entries.push(CategoryEntry { category, depth, post_count: 0 });
+        let post_count = counts.get(&id).copied().unwrap_or(0)
+            + add_subtree(Some(id), depth + 1, children, counts, entries);
+        entries[index].post_count = post_count;
+        total += post_count;
+    }
+    total
+}
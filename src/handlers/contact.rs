@@ -1,24 +1,32 @@
+use std::sync::Arc;
+
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::extract::State;
 use axum::Form;
 
+use crate::auth::CurrentUser;
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::error::Result;
-use crate::models::{ContactSubmission, CreateContactSubmission, Page, Profile};
+use crate::meta::PageMeta;
+use crate::models::{ContactSubmission, CreateContactSubmission, NavPage, Page, Profile};
 
 #[derive(Template)]
 #[template(path = "contact.html")]
 pub struct ContactTemplate {
-    pub nav_pages: Vec<Page>,
+    pub nav_pages: Vec<NavPage>,
     pub current_path: String,
     pub success_message: Option<String>,
     pub error: Option<String>,
+    pub meta: PageMeta,
     pub user: Option<Profile>,
 }
 
 pub async fn contact_page(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
 ) -> Result<impl IntoResponse> {
     let nav_pages = Page::list_nav_pages(&pool).await?;
 
@@ -27,12 +35,15 @@ pub async fn contact_page(
         current_path: "/contact/".to_string(),
         success_message: None,
         error: None,
-        user: None,
+        meta: contact_meta(&config),
+        user,
     })
 }
 
 pub async fn contact_submit(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Form(input): Form<CreateContactSubmission>,
 ) -> Result<impl IntoResponse> {
     let nav_pages = Page::list_nav_pages(&pool).await?;
@@ -44,18 +55,20 @@ pub async fn contact_submit(
             current_path: "/contact/".to_string(),
             success_message: None,
             error: Some("All fields are required.".to_string()),
-            user: None,
+            meta: contact_meta(&config),
+            user,
         });
     }
 
     // Create submission
-    match ContactSubmission::create(&pool, None, input, None, None).await {
+    match ContactSubmission::create(&pool, user.as_ref().map(|u| u.user_id), input, None, None).await {
         Ok(_) => Ok(ContactTemplate {
             nav_pages,
             current_path: "/contact/".to_string(),
             success_message: Some("Thank you for your message! I'll get back to you soon.".to_string()),
             error: None,
-            user: None,
+            meta: contact_meta(&config),
+            user,
         }),
         Err(e) => {
             tracing::error!("Failed to save contact submission: {:?}", e);
@@ -64,8 +77,13 @@ pub async fn contact_submit(
                 current_path: "/contact/".to_string(),
                 success_message: None,
                 error: Some("Sorry, there was an error. Please try again.".to_string()),
-                user: None,
+                meta: contact_meta(&config),
+                user,
             })
         }
     }
 }
+
+fn contact_meta(config: &Config) -> PageMeta {
+    PageMeta::new(config, "/contact/", "Contact", "Get in touch with Nestor Wheelock")
+}
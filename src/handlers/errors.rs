@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::extract::{Request, State};
+use axum::http::header::ACCEPT;
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+use crate::auth;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::{AppError, ErrorInfo};
+use crate::meta::PageMeta;
+use crate::models::{NavPage, Page, Profile};
+
+/// Set on every request by `SetRequestIdLayer` and echoed back in the response.
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+#[derive(Template)]
+#[template(path = "error.html")]
+pub struct ErrorTemplate {
+    pub status: u16,
+    pub title: String,
+    pub message: String,
+    pub request_id: Option<String>,
+    pub nav_pages: Vec<NavPage>,
+    pub current_path: String,
+    pub meta: PageMeta,
+    pub user: Option<Profile>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub status: u16,
+    pub error: String,
+    pub request_id: Option<String>,
+}
+
+/// Router fallback, so unknown URLs get the same 404 page as missing posts.
+pub async fn not_found() -> AppError {
+    AppError::NotFound("Page not found".to_string())
+}
+
+/// Replaces the plain-text body of `AppError` responses with a styled page, or with JSON
+/// when the client asked for it. Other responses pass through untouched.
+pub async fn render_error_page(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers().clone();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+
+    let Some(error) = response.extensions().get::<ErrorInfo>().cloned() else {
+        return response;
+    };
+    let request_id = headers
+        .get(X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    if wants_json(&headers) {
+        let body = ErrorBody { status: error.status.as_u16(), error: error.message, request_id };
+        return (error.status, Json(body)).into_response();
+    }
+
+    // The error may be the database itself, so the page must render without it
+    let nav_pages = Page::list_nav_pages(&pool).await.unwrap_or_default();
+    let user = auth::current_user(&pool, &config, &headers).await.unwrap_or(None);
+    let title = error.status.canonical_reason().unwrap_or("Error").to_string();
+    let template = ErrorTemplate {
+        status: error.status.as_u16(),
+        meta: PageMeta::new(&config, &path, title.clone(), error.message.clone()),
+        title,
+        message: error.message,
+        request_id,
+        nav_pages,
+        current_path: path,
+        user,
+    };
+    match template.render() {
+        Ok(html) => (error.status, Html(html)).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to render error page: {}", e);
+            response
+        }
+    }
+}
+
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"))
+}
@@ -1,19 +1,111 @@
+use std::sync::Arc;
+
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::extract::{Path, Query, State};
+use axum::response::Response;
 use serde::Deserialize;
 
+use crate::auth::CurrentUser;
+use crate::config::Config;
 use crate::db::DbPool;
-use crate::error::Result;
-use crate::models::{Category, Page, Post, PostMedia, Profile, Tag};
+use crate::error::{AppError, Result};
+use crate::models::{Category, NavPage, Page, Post, PostMedia, Profile, SlugRedirect, Tag};
 use crate::models::media::PostMediaWithItem;
+use crate::models::post;
+use crate::handlers::moved_permanently;
+use crate::markdown;
+use crate::meta::{PageMeta, SITE_DESCRIPTION, SITE_NAME};
+use crate::privacy::Viewer;
 
 #[derive(Deserialize)]
 pub struct FeedParams {
     pub page: Option<i64>,
     pub tag: Option<String>,
+    pub category: Option<String>,
+    /// Id of a section page listing posts; slugs are only unique among sibling pages
+    pub section: Option<i64>,
+}
+
+/// Which posts a listing shows. It is carried in `/htmx/feed/` URLs so infinite scroll
+/// keeps loading from the same listing instead of falling back to the homepage feed.
+pub enum FeedScope {
+    All,
+    Tag(Tag),
+    Category(Category),
+    /// A section page's configured category and/or tag
+    Page(Page),
+}
+
+impl FeedScope {
+    /// The listing a section page shows below its body, if it is configured with one.
+    pub fn for_page(page: &Page) -> Option<Self> {
+        page.lists_posts().then(|| FeedScope::Page(page.clone()))
+    }
+
+    /// Resolves `/htmx/feed/` parameters; unknown tags, categories and pages are a 404.
+    async fn from_params(pool: &DbPool, viewer: &Viewer<'_>, params: &FeedParams) -> Result<Self> {
+        if let Some(id) = params.section {
+            let not_found = || AppError::NotFound("Page not found".to_string());
+            let page = Page::find_by_id(pool, id).await?
+                .filter(|page| viewer.can_view_page(page))
+                .ok_or_else(not_found)?;
+            // The page itself is only reachable while every ancestor is published and visible
+            let ancestors = Page::ancestors(pool, page.id).await?;
+            if !ancestors.iter().all(|a| a.is_published && viewer.can_view_page(a)) {
+                return Err(not_found());
+            }
+            return FeedScope::for_page(&page).ok_or_else(not_found);
+        }
+        if let Some(ref slug) = params.tag {
+            let tag = Tag::find_by_slug(pool, slug).await?
+                .ok_or_else(|| AppError::NotFound("Tag not found".to_string()))?;
+            return Ok(FeedScope::Tag(tag));
+        }
+        if let Some(ref slug) = params.category {
+            let category = Category::find_by_slug(pool, slug).await?
+                .ok_or_else(|| AppError::NotFound("Category not found".to_string()))?;
+            return Ok(FeedScope::Category(category));
+        }
+        Ok(FeedScope::All)
+    }
+
+    pub async fn list_posts(&self, pool: &DbPool, viewer: &Viewer<'_>, limit: i64, offset: i64) -> Result<Vec<Post>> {
+        match self {
+            FeedScope::All => Post::list_public(pool, viewer, limit, offset).await,
+            FeedScope::Tag(tag) => Post::list_by_tag(pool, tag.id, viewer, limit, offset).await,
+            FeedScope::Category(cat) => Post::list_by_category(pool, cat.id, viewer, limit, offset).await,
+            FeedScope::Page(page) => Post::list_for_page(pool, page, viewer, limit, offset).await,
+        }
+    }
+
+    /// Section pages set their own page size; other listings use `PER_PAGE`.
+    fn per_page(&self) -> i64 {
+        match self {
+            FeedScope::Page(page) => i64::from(page.posts_per_page).clamp(1, MAX_PER_PAGE),
+            _ => PER_PAGE,
+        }
+    }
+
+    /// URL of the `/htmx/feed/` partial continuing this listing at `page`.
+    pub fn page_url(&self, page: i64) -> String {
+        match self {
+            FeedScope::All => format!("/htmx/feed/?page={}", page),
+            FeedScope::Tag(tag) => format!("/htmx/feed/?page={}&tag={}", page, tag.slug),
+            FeedScope::Category(cat) => format!("/htmx/feed/?page={}&category={}", page, cat.slug),
+            FeedScope::Page(section) => format!("/htmx/feed/?page={}&section={}", page, section.id),
+        }
+    }
 }
 
+const PER_PAGE: i64 = 10;
+/// Upper bound on a section page's `posts_per_page`.
+const MAX_PER_PAGE: i64 = 50;
+/// Highest `?page=` served; larger numbers would overflow the offset and are past the end
+/// of any real listing anyway.
+const MAX_PAGE: i64 = 10_000;
+const RELATED_TAGS: i64 = 8;
+
 #[derive(Clone)]
 pub struct PostWithMedia {
     pub id: i64,
@@ -24,15 +116,17 @@ pub struct PostWithMedia {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub featured_media: Option<PostMediaWithItem>,
     pub tags: Vec<Tag>,
+    /// Pre-escaped HTML excerpt with `<mark>`ed matches, set on search results
+    pub snippet: Option<String>,
 }
 
 impl PostWithMedia {
     pub fn preview(&self) -> String {
-        if self.body.len() > 280 {
-            format!("{}...", &self.body[..280])
-        } else {
-            self.body.clone()
-        }
+        markdown::preview(&self.body)
+    }
+
+    pub fn url(&self) -> String {
+        post::url(self.id, self.title.as_deref(), &self.body)
     }
 }
 
@@ -40,12 +134,19 @@ impl PostWithMedia {
 #[template(path = "feed.html")]
 pub struct FeedTemplate {
     pub posts: Vec<PostWithMedia>,
-    pub nav_pages: Vec<Page>,
+    pub nav_pages: Vec<NavPage>,
     pub current_tag: Option<Tag>,
+    /// Tags most often used together with `current_tag`
+    pub related_tags: Vec<Tag>,
+    pub current_category: Option<Category>,
+    /// Ancestors of `current_category`, root first
+    pub category_ancestors: Vec<Category>,
     pub current_path: String,
     pub show_dates: bool,
     pub has_next_page: bool,
-    pub next_page: i64,
+    /// `/htmx/feed/` URL loading the next page within the same listing
+    pub next_page_url: String,
+    pub meta: PageMeta,
     pub user: Option<Profile>,
 }
 
@@ -53,143 +154,187 @@ pub struct FeedTemplate {
 #[template(path = "partials/feed_items.html")]
 pub struct FeedItemsTemplate {
     pub posts: Vec<PostWithMedia>,
-    pub current_tag: Option<Tag>,
     pub show_dates: bool,
     pub has_next_page: bool,
-    pub next_page: i64,
+    pub next_page_url: String,
 }
 
 pub async fn homepage(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Query(params): Query<FeedParams>,
 ) -> Result<impl IntoResponse> {
-    let page = params.page.unwrap_or(1);
-    let per_page = 10i64;
-    let offset = (page - 1) * per_page;
+    let page = page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
 
-    let posts = Post::list_public(&pool, None, per_page + 1, offset).await?;
-    let has_next = posts.len() as i64 > per_page;
-    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
-
-    let posts_with_media = enrich_posts(&pool, posts).await?;
+    let scope = FeedScope::All;
+    let (posts, has_next) = load_page(&pool, &viewer, &scope, page).await?;
     let nav_pages = Page::list_nav_pages(&pool).await?;
 
     Ok(FeedTemplate {
-        posts: posts_with_media,
+        posts,
         nav_pages,
         current_tag: None,
+        related_tags: vec![],
+        current_category: None,
+        category_ancestors: vec![],
         current_path: "/".to_string(),
-        show_dates: false, // Anonymous users don't see dates
+        show_dates: viewer.can_see_dates(),
         has_next_page: has_next,
-        next_page: page + 1,
-        user: None,
+        next_page_url: scope.page_url(page + 1),
+        meta: PageMeta::new(&config, "/", SITE_NAME, SITE_DESCRIPTION),
+        user,
     })
 }
 
 pub async fn feed_partial(
     State(pool): State<DbPool>,
+    CurrentUser(user): CurrentUser,
     Query(params): Query<FeedParams>,
 ) -> Result<impl IntoResponse> {
-    let page = params.page.unwrap_or(1);
-    let per_page = 10i64;
-    let offset = (page - 1) * per_page;
+    let page = page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
 
-    let current_tag = if let Some(ref slug) = params.tag {
-        Tag::find_by_slug(&pool, slug).await?
-    } else {
-        None
-    };
-
-    let posts = if let Some(ref tag) = current_tag {
-        Post::list_by_tag(&pool, tag.id, None, per_page + 1, offset).await?
-    } else {
-        Post::list_public(&pool, None, per_page + 1, offset).await?
-    };
-
-    let has_next = posts.len() as i64 > per_page;
-    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
-    let posts_with_media = enrich_posts(&pool, posts).await?;
+    let scope = FeedScope::from_params(&pool, &viewer, &params).await?;
+    let (posts, has_next) = load_page(&pool, &viewer, &scope, page).await?;
 
     Ok(FeedItemsTemplate {
-        posts: posts_with_media,
-        current_tag,
-        show_dates: false,
+        posts,
+        show_dates: viewer.can_see_dates(),
         has_next_page: has_next,
-        next_page: page + 1,
+        next_page_url: scope.page_url(page + 1),
     })
 }
 
 pub async fn browse(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Query(params): Query<FeedParams>,
 ) -> Result<impl IntoResponse> {
-    homepage(State(pool), Query(params)).await
+    homepage(State(pool), State(config), CurrentUser(user), Query(params)).await
 }
 
 pub async fn category_feed(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Path(slug): Path<String>,
     Query(params): Query<FeedParams>,
-) -> Result<impl IntoResponse> {
-    let category = Category::find_by_slug(&pool, &slug).await?;
-    let page = params.page.unwrap_or(1);
-    let per_page = 10i64;
-    let offset = (page - 1) * per_page;
-
-    let posts = if let Some(ref cat) = category {
-        Post::list_by_category(&pool, cat.id, None, per_page + 1, offset).await?
-    } else {
-        vec![]
+) -> Result<Response> {
+    let Some(category) = Category::find_by_slug(&pool, &slug).await? else {
+        let renamed = match SlugRedirect::find(&pool, SlugRedirect::CATEGORY, &slug).await? {
+            Some(redirect) => Category::find_by_id(&pool, redirect.object_id).await?,
+            None => None,
+        };
+        return match renamed {
+            Some(category) => {
+                Ok(moved_permanently(&renamed_url(&format!("/category/{}/", category.slug), params.page)))
+            }
+            None => Err(AppError::NotFound("Category not found".to_string())),
+        };
     };
+    let page = page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
 
-    let has_next = posts.len() as i64 > per_page;
-    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
-    let posts_with_media = enrich_posts(&pool, posts).await?;
+    let scope = FeedScope::Category(category.clone());
+    let (posts, has_next) = load_page(&pool, &viewer, &scope, page).await?;
+    let category_ancestors = Category::ancestors(&pool, category.id).await?;
     let nav_pages = Page::list_nav_pages(&pool).await?;
+    let meta = PageMeta::new(
+        &config,
+        &format!("/category/{}/", category.slug),
+        category.name.clone(),
+        category.description.clone().filter(|d| !d.is_empty()).unwrap_or_else(|| format!("Posts about {}", category.name)),
+    );
 
     Ok(FeedTemplate {
-        posts: posts_with_media,
+        posts,
         nav_pages,
         current_tag: None,
+        related_tags: vec![],
+        current_category: Some(category),
+        category_ancestors,
         current_path: format!("/category/{}/", slug),
-        show_dates: false,
+        show_dates: viewer.can_see_dates(),
         has_next_page: has_next,
-        next_page: page + 1,
-        user: None,
-    })
+        next_page_url: scope.page_url(page + 1),
+        meta,
+        user,
+    }
+    .into_response())
 }
 
 pub async fn tag_feed(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Path(slug): Path<String>,
     Query(params): Query<FeedParams>,
-) -> Result<impl IntoResponse> {
-    let tag = Tag::find_by_slug(&pool, &slug).await?;
-    let page = params.page.unwrap_or(1);
-    let per_page = 10i64;
-    let offset = (page - 1) * per_page;
-
-    let posts = if let Some(ref t) = tag {
-        Post::list_by_tag(&pool, t.id, None, per_page + 1, offset).await?
-    } else {
-        vec![]
+) -> Result<Response> {
+    let Some(tag) = Tag::find_by_slug(&pool, &slug).await? else {
+        let renamed = match SlugRedirect::find(&pool, SlugRedirect::TAG, &slug).await? {
+            Some(redirect) => Tag::find_by_id(&pool, redirect.object_id).await?,
+            None => None,
+        };
+        return match renamed {
+            Some(tag) => Ok(moved_permanently(&renamed_url(&format!("/tags/{}/", tag.slug), params.page))),
+            None => Err(AppError::NotFound("Tag not found".to_string())),
+        };
     };
+    let page = page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
 
-    let has_next = posts.len() as i64 > per_page;
-    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
-    let posts_with_media = enrich_posts(&pool, posts).await?;
+    let scope = FeedScope::Tag(tag.clone());
+    let (posts, has_next) = load_page(&pool, &viewer, &scope, page).await?;
+    let related_tags = Tag::list_related(&pool, tag.id, &viewer, RELATED_TAGS).await?;
     let nav_pages = Page::list_nav_pages(&pool).await?;
+    let meta = PageMeta::new(
+        &config,
+        &format!("/tags/{}/", tag.slug),
+        tag.name.clone(),
+        tag.description.clone().filter(|d| !d.is_empty()).unwrap_or_else(|| format!("Posts tagged {}", tag.name)),
+    );
 
     Ok(FeedTemplate {
-        posts: posts_with_media,
+        posts,
         nav_pages,
-        current_tag: tag,
+        current_tag: Some(tag),
+        related_tags,
+        current_category: None,
+        category_ancestors: vec![],
         current_path: format!("/tags/{}/", slug),
-        show_dates: false,
+        show_dates: viewer.can_see_dates(),
         has_next_page: has_next,
-        next_page: page + 1,
-        user: None,
-    })
+        next_page_url: scope.page_url(page + 1),
+        meta,
+        user,
+    }
+    .into_response())
+}
+
+/// Current URL of a renamed listing, keeping the page number.
+fn renamed_url(path: &str, page: Option<i64>) -> String {
+    match page {
+        Some(page) => format!("{}?page={}", path, page),
+        None => path.to_string(),
+    }
+}
+
+/// `?page=` parameter limited to `1..=MAX_PAGE`.
+pub fn page_number(page: Option<i64>) -> i64 {
+    page.unwrap_or(1).clamp(1, MAX_PAGE)
+}
+
+/// One page of a listing, enriched for the feed cards, and whether another page follows.
+pub async fn load_page(pool: &DbPool, viewer: &Viewer<'_>, scope: &FeedScope, page: i64) -> Result<(Vec<PostWithMedia>, bool)> {
+    let per_page = scope.per_page();
+    let offset = (page - 1) * per_page;
+    let posts = scope.list_posts(pool, viewer, per_page + 1, offset).await?;
+    let has_next = posts.len() as i64 > per_page;
+    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
+    Ok((enrich_posts(pool, posts).await?, has_next))
 }
 
 async fn enrich_posts(pool: &DbPool, posts: Vec<Post>) -> Result<Vec<PostWithMedia>> {
@@ -206,6 +351,7 @@ async fn enrich_posts(pool: &DbPool, posts: Vec<Post>) -> Result<Vec<PostWithMed
             created_at: post.created_at,
             featured_media,
             tags,
+            snippet: None,
         });
     }
     Ok(result)
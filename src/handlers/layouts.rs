@@ -0,0 +1,87 @@
+use std::ops::Deref;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::response::Response;
+
+use crate::handlers::pages::PageTemplate;
+use crate::models::Page;
+
+/// Page layouts editors pick through `Page.template` in Django. Every layout renders the
+/// same `PageTemplate` context (reached through `Deref`), so adding one takes a template
+/// under `templates/layouts/`, a `layout!` struct and a `LAYOUTS` entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageLayout {
+    /// `page.html`: sidebar and content card
+    Default,
+    FullWidth,
+    Gallery,
+    Links,
+    Landing,
+}
+
+/// Layout names accepted in `Page.template`, after `normalize_name`.
+const LAYOUTS: &[(&str, PageLayout)] = &[
+    ("default", PageLayout::Default),
+    ("page", PageLayout::Default),
+    ("full-width", PageLayout::FullWidth),
+    ("gallery", PageLayout::Gallery),
+    ("links", PageLayout::Links),
+    ("link-list", PageLayout::Links),
+    ("landing", PageLayout::Landing),
+];
+
+impl PageLayout {
+    /// The layout named by the page's `template`, or the default one when it is blank or
+    /// unknown (logged so a typo in Django doesn't go unnoticed).
+    pub fn for_page(page: &Page) -> Self {
+        let Some(name) = page.template.as_deref().map(normalize_name).filter(|n| !n.is_empty()) else {
+            return PageLayout::Default;
+        };
+        match LAYOUTS.iter().find(|(known, _)| *known == name) {
+            Some((_, layout)) => *layout,
+            None => {
+                tracing::warn!("Page {:?} uses unknown layout {:?}; rendering the default", page.slug, name);
+                PageLayout::Default
+            }
+        }
+    }
+
+    pub fn render(self, context: PageTemplate) -> Response {
+        match self {
+            PageLayout::Default => context.into_response(),
+            PageLayout::FullWidth => FullWidthLayout(context).into_response(),
+            PageLayout::Gallery => GalleryLayout(context).into_response(),
+            PageLayout::Links => LinksLayout(context).into_response(),
+            PageLayout::Landing => LandingLayout(context).into_response(),
+        }
+    }
+}
+
+/// Accepts Django-style values too: `"pages/Full_Width.html"` names `full-width`.
+fn normalize_name(template: &str) -> String {
+    let name = template.trim().rsplit('/').next().unwrap_or_default();
+    let name = name.strip_suffix(".html").unwrap_or(name);
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+macro_rules! layout {
+    ($name:ident, $path:literal) => {
+        #[derive(Template)]
+        #[template(path = $path)]
+        pub struct $name(PageTemplate);
+
+        impl Deref for $name {
+            type Target = PageTemplate;
+
+            fn deref(&self) -> &PageTemplate {
+                &self.0
+            }
+        }
+    };
+}
+
+layout!(FullWidthLayout, "layouts/full_width.html");
+layout!(GalleryLayout, "layouts/gallery.html");
+layout!(LinksLayout, "layouts/links.html");
+layout!(LandingLayout, "layouts/landing.html");
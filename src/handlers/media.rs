@@ -0,0 +1,109 @@
+use std::path::{Component, Path as FsPath};
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use crate::auth::CurrentUser;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::MediaLibrary;
+use crate::privacy::Viewer;
+use crate::share;
+
+/// nginx sets this on proxied `/media/` requests when it can serve the file itself.
+const SENDFILE_TYPE_HEADER: &str = "x-sendfile-type";
+/// Internal nginx location aliased to the media volume (see `nginx/nginx.conf`).
+const INTERNAL_MEDIA_LOCATION: &str = "/protected-media/";
+/// Everything but unreserved URL characters, so `?`, `#` and `%` in file names reach nginx
+/// escaped (it splits `X-Accel-Redirect` on `?` and unescapes it).
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+#[derive(Deserialize)]
+pub struct MediaParams {
+    pub share: Option<String>,
+}
+
+pub async fn serve_media(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(path): Path<String>,
+    Query(params): Query<MediaParams>,
+    request: Request,
+) -> Result<Response> {
+    // Only plain relative paths inside the media root
+    if !FsPath::new(&path).components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(AppError::NotFound("Media not found".to_string()));
+    }
+
+    let mut viewer = Viewer::new(user.as_ref());
+    if let Some(token) = params.share {
+        if let Some(share) = share::resolve(&pool, &config, &token).await? {
+            viewer = viewer.with_share(share.post_id);
+        }
+    }
+    if let Some(media) = MediaLibrary::find_by_file(&pool, &path).await? {
+        // Same 404 as a missing file so private media doesn't reveal itself
+        if !viewer.can_view_media(&pool, &media).await? {
+            return Err(AppError::NotFound("Media not found".to_string()));
+        }
+    }
+    let cache_control = if user.is_some() || viewer.shared_post_id.is_some() {
+        HeaderValue::from_static("private, max-age=3600")
+    } else {
+        HeaderValue::from_static("public, max-age=604800")
+    };
+
+    let mut response = if behind_accel_proxy(request.headers()) {
+        let mut response = Response::default();
+        let target = format!("{}{}", INTERNAL_MEDIA_LOCATION, accel_path(&path));
+        response.headers_mut().insert(
+            "x-accel-redirect",
+            HeaderValue::from_str(&target).map_err(|e| AppError::Internal(e.to_string()))?,
+        );
+        response
+    } else {
+        let file = config.media_root.join(&path);
+        match ServeFile::new(file).oneshot(request).await {
+            Ok(response) => response.into_response(),
+            Err(never) => match never {},
+        }
+    };
+    response.headers_mut().insert(CACHE_CONTROL, cache_control);
+    Ok(response)
+}
+
+/// The (already decoded) media path with each segment percent-encoded again.
+fn accel_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn behind_accel_proxy(headers: &HeaderMap) -> bool {
+    headers
+        .get(SENDFILE_TYPE_HEADER)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"X-Accel-Redirect"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accel_path_escapes_each_segment() {
+        assert_eq!(accel_path("uploads/2024/reef.jpg"), "uploads/2024/reef.jpg");
+        assert_eq!(accel_path("uploads/what?.jpg"), "uploads/what%3F.jpg");
+        assert_eq!(accel_path("uploads/100% #1.jpg"), "uploads/100%25%20%231.jpg");
+        assert_eq!(accel_path("uploads/cañón.jpg"), "uploads/ca%C3%B1%C3%B3n.jpg");
+    }
+}
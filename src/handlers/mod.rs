@@ -1,30 +1,77 @@
 pub mod feed;
+pub mod categories;
+pub mod tags;
 pub mod posts;
 pub mod pages;
+pub mod layouts;
+pub mod syndication;
+pub mod sitemap;
 pub mod search;
 pub mod contact;
+pub mod media;
+pub mod cards;
+pub mod errors;
 
 use axum::{
+    http::{header::LOCATION, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
 
-use crate::db::DbPool;
+use crate::state::AppState;
 
-pub fn public_routes() -> Router<DbPool> {
+/// Response header keeping previews and other private renders out of search indexes.
+pub const X_ROBOTS_TAG: &str = "x-robots-tag";
+
+pub fn public_routes() -> Router<AppState> {
     Router::new()
         // Feed routes
         .route("/", get(feed::homepage))
         .route("/htmx/feed/", get(feed::feed_partial))
         .route("/browse/", get(feed::browse))
+        .route("/categories/", get(categories::category_index))
         .route("/category/:slug/", get(feed::category_feed))
+        .route("/tags/", get(tags::tag_index))
         .route("/tags/:slug/", get(feed::tag_feed))
+        .route("/htmx/tag-cloud/", get(tags::tag_cloud))
+        // Syndication (RSS, Atom and JSON Feed)
+        .route("/feed.xml", get(syndication::site_feed))
+        .route("/atom.xml", get(syndication::site_feed))
+        .route("/feed.json", get(syndication::site_feed))
+        .route("/tags/:slug/feed.xml", get(syndication::tag_feed))
+        .route("/tags/:slug/atom.xml", get(syndication::tag_feed))
+        .route("/tags/:slug/feed.json", get(syndication::tag_feed))
+        .route("/category/:slug/feed.xml", get(syndication::category_feed))
+        .route("/category/:slug/atom.xml", get(syndication::category_feed))
+        .route("/category/:slug/feed.json", get(syndication::category_feed))
+        // Crawlers
+        .route("/sitemap.xml", get(sitemap::sitemap_index))
+        .route("/sitemaps/:file", get(sitemap::sitemap_section))
+        .route("/robots.txt", get(sitemap::robots_txt))
         // Post routes
         .route("/posts/:id/", get(posts::detail))
+        .route("/posts/:id/:slug/", get(posts::detail))
+        .route("/posts/:id/preview/", get(posts::preview))
+        .route("/posts/:id/card.png", get(cards::post_card))
+        .route("/posts/:id/share/", post(posts::create_share_link))
+        .route("/posts/:id/share/revoke/", post(posts::revoke_share_link))
+        // Media (gated by the visibility of the posts using it)
+        .route("/media/*path", get(media::serve_media))
         // Search
         .route("/search/", get(search::search_page))
+        .route("/htmx/search/", get(search::search_partial))
         // Contact
         .route("/contact/", get(contact::contact_page).post(contact::contact_submit))
-        // Static pages (catch-all at end)
-        .route("/:slug/", get(pages::page_detail))
+        // Page previews
+        .route("/pages/:id/preview/", get(pages::preview))
+        // Static pages, nested as /parent/child/ (catch-all at end)
+        .route("/*path", get(pages::page_detail))
+        .fallback(errors::not_found)
+}
+
+/// 301 to the current URL of something that moved. (`Redirect::permanent` is a 308, which
+/// some crawlers don't treat as a permanent move of the old URL.)
+pub fn moved_permanently(location: &str) -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(LOCATION, location.to_string())]).into_response()
 }
@@ -1,39 +1,179 @@
+use std::sync::Arc;
+
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::response::Response;
 
+use crate::auth::CurrentUser;
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::error::{AppError, Result};
-use crate::models::{Page, Post, Profile};
-use crate::handlers::feed::PostWithMedia;
+use crate::models::{NavPage, Page, Profile};
+use crate::handlers::feed::{self, FeedParams, FeedScope, PostWithMedia};
+use crate::handlers::layouts::PageLayout;
+use crate::handlers::{moved_permanently, X_ROBOTS_TAG};
+use crate::markdown;
+use crate::meta::{PageMeta, SITE_DESCRIPTION};
+use crate::privacy::Viewer;
 
 #[derive(Template)]
 #[template(path = "page.html")]
 pub struct PageTemplate {
     pub page: Page,
+    /// Sanitized HTML rendered from the Markdown body
+    pub body_html: Option<String>,
     pub posts: Vec<PostWithMedia>,
-    pub nav_pages: Vec<Page>,
+    pub nav_pages: Vec<NavPage>,
     pub current_path: String,
+    /// Ancestor pages, root first
+    pub breadcrumbs: Vec<NavLink>,
+    /// Sub-navigation of the page's own children
+    pub children: Vec<NavLink>,
+    pub show_dates: bool,
+    /// Current page of the post listing, for layouts with numbered pagination
+    pub page_number: i64,
+    pub has_next_page: bool,
+    pub next_page_url: String,
+    pub is_preview: bool,
+    pub meta: PageMeta,
     pub user: Option<Profile>,
 }
 
+pub struct NavLink {
+    pub title: String,
+    pub url: String,
+}
+
 pub async fn page_detail(
     State(pool): State<DbPool>,
-    Path(slug): Path<String>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(path): Path<String>,
+    Query(params): Query<FeedParams>,
+) -> Result<Response> {
+    let viewer = Viewer::new(user.as_ref());
+    let not_found = || AppError::NotFound("Page not found".to_string());
+    let slugs: Vec<&str> = path.strip_suffix('/').ok_or_else(not_found)?.split('/').collect();
+
+    // Walk the page tree one segment at a time, so /parent/child/ only matches that nesting
+    let mut page: Option<Page> = None;
+    for slug in &slugs {
+        let parent_id = page.as_ref().map(|p| p.id);
+        page = Page::find_child(&pool, parent_id, slug).await?
+            .filter(|page| viewer.can_view_page(page));
+        if page.is_none() {
+            break;
+        }
+    }
+
+    let Some(page) = page else {
+        // Child pages used to be served at /slug/; send those links to the nested URL,
+        // unless pages under different parents now share the slug
+        if let [slug] = slugs[..] {
+            let nested: Vec<Page> = Page::list_nested_by_slug(&pool, slug).await?
+                .into_iter()
+                .filter(|page| viewer.can_view_page(page))
+                .collect();
+            if let [nested] = &nested[..] {
+                let ancestors = Page::ancestors(&pool, nested.id).await?;
+                return Ok(moved_permanently(&page_url(&ancestors, nested)));
+            }
+        }
+        return Err(not_found());
+    };
+
+    let template = page_template(&pool, &config, page, feed::page_number(params.page), false, user).await?;
+    Ok(PageLayout::for_page(&template.page).render(template))
+}
+
+/// Renders a page, unpublished ones included, with a preview banner (admins only).
+pub async fn preview(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
 ) -> Result<impl IntoResponse> {
-    let page = Page::find_by_slug(&pool, &slug).await?
+    if !user.as_ref().is_some_and(|u| u.is_admin()) {
+        return Err(AppError::NotFound("Page not found".to_string()));
+    }
+    let page = Page::find_for_preview(&pool, id).await?
         .ok_or_else(|| AppError::NotFound("Page not found".to_string()))?;
 
-    // Check if page pulls posts from category or tag
-    let posts = vec![]; // TODO: implement post pulling for pages
+    let template = page_template(&pool, &config, page, 1, true, user).await?;
+    let layout = PageLayout::for_page(&template.page);
+    Ok(([(X_ROBOTS_TAG, "noindex, nofollow")], layout.render(template)))
+}
+
+async fn page_template(
+    pool: &DbPool,
+    config: &Config,
+    page: Page,
+    page_number: i64,
+    is_preview: bool,
+    user: Option<Profile>,
+) -> Result<PageTemplate> {
+    let viewer = Viewer::new(user.as_ref());
+    let (posts, has_next_page, next_page_url) = match FeedScope::for_page(&page) {
+        Some(scope) => {
+            let (posts, has_next) = feed::load_page(pool, &viewer, &scope, page_number).await?;
+            (posts, has_next, scope.page_url(page_number + 1))
+        }
+        None => (vec![], false, String::new()),
+    };
 
-    let nav_pages = Page::list_nav_pages(&pool).await?;
+    let ancestors = Page::ancestors(pool, page.id).await?;
+    let current_path = page_url(&ancestors, &page);
+    let breadcrumbs = ancestors
+        .iter()
+        .enumerate()
+        .map(|(i, ancestor)| NavLink {
+            title: ancestor.title.clone(),
+            url: page_url(&ancestors[..i], ancestor),
+        })
+        .collect();
+    let children = Page::list_children(pool, page.id).await?
+        .into_iter()
+        .filter(|child| viewer.can_view_page(child))
+        .map(|child| NavLink {
+            url: format!("{}{}/", current_path, child.slug),
+            title: child.title,
+        })
+        .collect();
+    let nav_pages = Page::list_nav_pages(pool).await?;
+
+    let body_html = page.body.as_deref()
+        .map(|body| markdown::render_cached("page", page.id, page.updated_at, body));
+    let description = page.body.as_deref()
+        .map(markdown::preview)
+        .filter(|preview| !preview.is_empty())
+        .unwrap_or_else(|| SITE_DESCRIPTION.to_string());
+    let meta = PageMeta::new(config, &current_path, page.title.clone(), description);
 
     Ok(PageTemplate {
         page,
+        body_html,
         posts,
         nav_pages,
-        current_path: format!("/{}/", slug),
-        user: None,
+        current_path,
+        breadcrumbs,
+        children,
+        show_dates: viewer.can_see_dates(),
+        page_number,
+        has_next_page,
+        next_page_url,
+        is_preview,
+        meta,
+        user,
     })
 }
+
+/// `/parent/child/` URL of a page given its ancestors, root first.
+fn page_url(ancestors: &[Page], page: &Page) -> String {
+    let mut url = String::from("/");
+    for slug in ancestors.iter().chain([page]).map(|p| p.slug.as_str()) {
+        url.push_str(slug);
+        url.push('/');
+    }
+    url
+}
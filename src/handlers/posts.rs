@@ -1,49 +1,211 @@
+use std::sync::Arc;
+
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, RawQuery, State};
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Form, Json};
+use serde::{Deserialize, Serialize};
 
+use crate::auth::CurrentUser;
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::error::{AppError, Result};
-use crate::models::{Category, Page, Post, PostMedia, Profile, Tag};
+use crate::models::{Category, NavPage, Page, Post, PostMedia, Profile, RevokedShareLink, Tag};
 use crate::models::media::PostMediaWithItem;
+use crate::privacy::Viewer;
+use crate::share::{self, ShareToken};
+use crate::handlers::{cards, moved_permanently, X_ROBOTS_TAG};
+use crate::markdown;
+use crate::meta::PageMeta;
+
+/// `/posts/:id/` or `/posts/:id/:slug/`
+#[derive(Deserialize)]
+pub struct PostPath {
+    pub id: i64,
+    pub slug: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DetailParams {
+    pub share: Option<String>,
+}
 
 #[derive(Template)]
 #[template(path = "detail.html")]
 pub struct DetailTemplate {
     pub post: Post,
+    /// Sanitized HTML rendered from the Markdown body
+    pub body_html: String,
     pub media: Vec<PostMediaWithItem>,
     pub tags: Vec<Tag>,
     pub category: Option<Category>,
-    pub nav_pages: Vec<Page>,
+    /// Ancestors of `category`, root first
+    pub category_ancestors: Vec<Category>,
+    pub nav_pages: Vec<NavPage>,
     pub current_path: String,
     pub show_dates: bool,
+    /// Share token the post was opened with, forwarded to media URLs
+    pub share_token: Option<String>,
+    pub is_preview: bool,
+    pub meta: PageMeta,
     pub user: Option<Profile>,
 }
 
 pub async fn detail(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(path): Path<PostPath>,
+    Query(params): Query<DetailParams>,
+    RawQuery(query): RawQuery,
+) -> Result<Response> {
+    let mut viewer = Viewer::new(user.as_ref());
+    let post = Post::find_by_id(&pool, path.id).await?
+        .ok_or_else(|| AppError::NotFound("Post not found".to_string()))?;
+
+    let share_token = match params.share {
+        Some(token) => share::resolve(&pool, &config, &token).await?
+            .filter(|share| share.post_id == post.id)
+            .map(|_| token),
+        None => None,
+    };
+    if share_token.is_some() {
+        viewer = viewer.with_share(post.id);
+    }
+    viewer.require_post(&pool, &post).await?;
+
+    // Id-only and outdated slug URLs move to the canonical one, keeping e.g. the share token.
+    // Only after the access check, since the slug gives away the title.
+    if path.slug.as_deref() != Some(post.slug().as_str()) {
+        let location = match query {
+            Some(query) => format!("{}?{}", post.url(), query),
+            None => post.url(),
+        };
+        return Ok(moved_permanently(&location));
+    }
+
+    let show_dates = viewer.can_see_dates();
+    Ok(detail_template(&pool, &config, post, show_dates, share_token, false, user).await?.into_response())
+}
+
+/// Renders a post, drafts included, with a preview banner (admins only).
+pub async fn preview(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Path(id): Path<i64>,
 ) -> Result<impl IntoResponse> {
-    let post = Post::find_by_id(&pool, id).await?
+    if !user.as_ref().is_some_and(|u| u.is_admin()) {
+        return Err(AppError::NotFound("Post not found".to_string()));
+    }
+    let post = Post::find_for_preview(&pool, id).await?
         .ok_or_else(|| AppError::NotFound("Post not found".to_string()))?;
 
-    let media = PostMedia::list_for_post(&pool, post.id).await?;
-    let tags = Tag::list_for_post(&pool, post.id).await?;
+    let template = detail_template(&pool, &config, post, true, None, true, user).await?;
+    Ok(([(X_ROBOTS_TAG, "noindex, nofollow")], template))
+}
+
+async fn detail_template(
+    pool: &DbPool,
+    config: &Config,
+    post: Post,
+    show_dates: bool,
+    share_token: Option<String>,
+    is_preview: bool,
+    user: Option<Profile>,
+) -> Result<DetailTemplate> {
+    let media = PostMedia::list_for_post(pool, post.id).await?;
+    let tags = Tag::list_for_post(pool, post.id).await?;
     let category = if let Some(cat_id) = post.category_id {
-        Category::find_by_id(&pool, cat_id).await?
+        Category::find_by_id(pool, cat_id).await?
     } else {
         None
     };
-    let nav_pages = Page::list_nav_pages(&pool).await?;
+    let category_ancestors = match category {
+        Some(ref cat) => Category::ancestors(pool, cat.id).await?,
+        None => vec![],
+    };
+    let nav_pages = Page::list_nav_pages(pool).await?;
+
+    let current_path = post.url();
+    let description = post.preview();
+    let meta = PageMeta::new(config, &current_path, post.title.clone().unwrap_or_else(|| description.clone()), description)
+        .article(post.created_at, post.updated_at)
+        .with_image(config, media.first())
+        .with_card(cards::card_url(config, &post));
 
     Ok(DetailTemplate {
+        current_path,
+        body_html: markdown::render_cached("post", post.id, post.updated_at, &post.body),
         post,
         media,
         tags,
         category,
+        category_ancestors,
         nav_pages,
-        current_path: format!("/posts/{}/", id),
-        show_dates: false,
-        user: None,
+        show_dates,
+        share_token,
+        is_preview,
+        meta,
+        user,
     })
 }
+
+#[derive(Deserialize)]
+pub struct CreateShareLink {
+    pub days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ShareLinkResponse {
+    pub url: String,
+    pub token_id: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeShareLink {
+    pub token_id: String,
+}
+
+/// Mints a share link for a post (admins only).
+pub async fn create_share_link(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    Form(input): Form<CreateShareLink>,
+) -> Result<Json<ShareLinkResponse>> {
+    if !user.as_ref().is_some_and(|u| u.is_admin()) {
+        return Err(AppError::NotFound("Post not found".to_string()));
+    }
+    let post = Post::find_by_id(&pool, id).await?
+        .ok_or_else(|| AppError::NotFound("Post not found".to_string()))?;
+    let secret = config.share_link_secret.as_deref()
+        .ok_or_else(|| AppError::Internal("SHARE_LINK_SECRET is not configured".to_string()))?;
+
+    let days = input.days.unwrap_or(7).clamp(1, 365);
+    let share = ShareToken::new(post.id, chrono::Duration::days(days));
+
+    Ok(Json(ShareLinkResponse {
+        url: format!("{}?share={}", post.url(), share.sign(secret.as_bytes())),
+        token_id: share.token_id,
+        expires_at: share.expires_at,
+    }))
+}
+
+/// Adds a share link to the revocation denylist (admins only).
+pub async fn revoke_share_link(
+    State(pool): State<DbPool>,
+    CurrentUser(user): CurrentUser,
+    Path(id): Path<i64>,
+    Form(input): Form<RevokeShareLink>,
+) -> Result<StatusCode> {
+    if !user.as_ref().is_some_and(|u| u.is_admin()) {
+        return Err(AppError::NotFound("Post not found".to_string()));
+    }
+    RevokedShareLink::create(&pool, &input.token_id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
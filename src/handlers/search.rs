@@ -1,12 +1,20 @@
+use std::sync::Arc;
+
 use askama::Template;
 use askama_axum::IntoResponse;
 use axum::extract::{Query, State};
+use chrono::NaiveDate;
 use serde::Deserialize;
 
+use crate::auth::CurrentUser;
+use crate::config::Config;
 use crate::db::DbPool;
 use crate::error::Result;
-use crate::models::{Page, Post, PostMedia, Profile, Tag};
-use crate::handlers::feed::PostWithMedia;
+use crate::models::{NavPage, Page, Post, PostMedia, Profile, Tag};
+use crate::models::search::{SearchFilters, SearchHit, HIGHLIGHT_START, HIGHLIGHT_STOP};
+use crate::handlers::feed::{self, FeedItemsTemplate, PostWithMedia};
+use crate::meta::PageMeta;
+use crate::privacy::Viewer;
 
 #[derive(Deserialize)]
 pub struct SearchParams {
@@ -19,38 +27,103 @@ pub struct SearchParams {
 pub struct SearchTemplate {
     pub posts: Vec<PostWithMedia>,
     pub query: String,
-    pub nav_pages: Vec<Page>,
+    pub total: i64,
+    pub nav_pages: Vec<NavPage>,
     pub current_path: String,
-    pub current_tag: Option<Tag>,
     pub show_dates: bool,
     pub has_next_page: bool,
-    pub next_page: i64,
+    pub next_page_url: String,
+    /// Numbered page links for clients without JavaScript; `None` marks a gap
+    pub page_links: Vec<Option<PageLink>>,
+    /// Problem with the query's operators, shown above the (empty) results
+    pub error: Option<String>,
+    pub meta: PageMeta,
     pub user: Option<Profile>,
 }
 
+pub struct PageLink {
+    pub number: i64,
+    pub url: String,
+    pub is_current: bool,
+}
+
+/// One page of search results, shared by the full page and the htmx partial.
+struct SearchResults {
+    posts: Vec<PostWithMedia>,
+    total: i64,
+    has_next: bool,
+    error: Option<String>,
+}
+
+const PER_PAGE: i64 = 10;
+
 pub async fn search_page(
     State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
     Query(params): Query<SearchParams>,
 ) -> Result<impl IntoResponse> {
     let query = params.q.unwrap_or_default();
-    let page = params.page.unwrap_or(1);
-    let per_page = 10i64;
-    let offset = (page - 1) * per_page;
-
-    let posts = if !query.is_empty() {
-        Post::search(&pool, &query, None, per_page + 1, offset).await?
-    } else {
-        vec![]
+    let page = feed::page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
+
+    let results = run_search(&pool, &viewer, &query, page).await?;
+    let nav_pages = Page::list_nav_pages(&pool).await?;
+
+    Ok(SearchTemplate {
+        page_links: page_links(&query, page, results.total),
+        next_page_url: search_url("/htmx/search/", &query, page + 1),
+        posts: results.posts,
+        query,
+        total: results.total,
+        nav_pages,
+        current_path: "/search/".to_string(),
+        show_dates: viewer.can_see_dates(),
+        has_next_page: results.has_next,
+        error: results.error,
+        meta: PageMeta::new(&config, "/search/", "Search", "Search posts by text, tag, category and date"),
+        user,
+    })
+}
+
+/// Next page of search results for infinite scroll.
+pub async fn search_partial(
+    State(pool): State<DbPool>,
+    CurrentUser(user): CurrentUser,
+    Query(params): Query<SearchParams>,
+) -> Result<impl IntoResponse> {
+    let query = params.q.unwrap_or_default();
+    let page = feed::page_number(params.page);
+    let viewer = Viewer::new(user.as_ref());
+
+    let results = run_search(&pool, &viewer, &query, page).await?;
+
+    Ok(FeedItemsTemplate {
+        posts: results.posts,
+        show_dates: viewer.can_see_dates(),
+        has_next_page: results.has_next,
+        next_page_url: search_url("/htmx/search/", &query, page + 1),
+    })
+}
+
+async fn run_search(pool: &DbPool, viewer: &Viewer<'_>, query: &str, page: i64) -> Result<SearchResults> {
+    let offset = (page - 1) * PER_PAGE;
+
+    let filters = match parse_query(query) {
+        Ok(filters) if !filters.is_empty() => filters,
+        Ok(_) => return Ok(SearchResults { posts: vec![], total: 0, has_next: false, error: None }),
+        Err(e) => return Ok(SearchResults { posts: vec![], total: 0, has_next: false, error: Some(e) }),
     };
 
-    let has_next = posts.len() as i64 > per_page;
-    let posts: Vec<Post> = posts.into_iter().take(per_page as usize).collect();
+    let hits = Post::search(pool, &filters, viewer, PER_PAGE + 1, offset).await?;
+    let total = Post::search_count(pool, &filters, viewer).await?;
+    let has_next = hits.len() as i64 > PER_PAGE;
 
-    let mut posts_with_media = Vec::new();
-    for post in posts {
-        let featured_media = PostMedia::get_featured_for_post(&pool, post.id).await?;
-        let tags = Tag::list_for_post(&pool, post.id).await?;
-        posts_with_media.push(PostWithMedia {
+    let mut posts = Vec::new();
+    for SearchHit { post, headline } in hits.into_iter().take(PER_PAGE as usize) {
+        let featured_media = PostMedia::get_featured_for_post(pool, post.id).await?;
+        let tags = Tag::list_for_post(pool, post.id).await?;
+        posts.push(PostWithMedia {
             id: post.id,
             title: post.title,
             body: post.body,
@@ -59,20 +132,244 @@ pub async fn search_page(
             created_at: post.created_at,
             featured_media,
             tags,
+            snippet: headline.as_deref().map(highlight),
         });
     }
 
-    let nav_pages = Page::list_nav_pages(&pool).await?;
+    Ok(SearchResults { posts, total, has_next, error: None })
+}
 
-    Ok(SearchTemplate {
-        posts: posts_with_media,
-        query,
-        nav_pages,
-        current_path: "/search/".to_string(),
-        current_tag: None,
-        show_dates: false,
-        has_next_page: has_next,
-        next_page: page + 1,
-        user: None,
-    })
+fn search_url(path: &str, query: &str, page: i64) -> String {
+    let params = serde_urlencoded::to_string([("q", query), ("page", &page.to_string())])
+        .unwrap_or_default();
+    format!("{}?{}", path, params)
+}
+
+/// First, last and the pages around the current one, with gaps in between.
+fn page_links(query: &str, current: i64, total: i64) -> Vec<Option<PageLink>> {
+    let last = (total + PER_PAGE - 1) / PER_PAGE;
+    if last <= 1 {
+        return vec![];
+    }
+
+    let mut links = Vec::new();
+    let mut previous = 0;
+    for number in 1..=last {
+        if number != 1 && number != last && (number - current).abs() > 2 {
+            continue;
+        }
+        if number > previous + 1 {
+            links.push(None);
+        }
+        links.push(Some(PageLink {
+            number,
+            url: search_url("/search/", query, number),
+            is_current: number == current,
+        }));
+        previous = number;
+    }
+    links
+}
+
+/// Parses the search box language: free text mixed with `tag:`, `category:`,
+/// `location:`, `before:`, `after:` and `platform:` operators. Operator values may be
+/// quoted (`location:"playa del carmen"`); anything else is passed on as full-text terms.
+pub fn parse_query(input: &str) -> std::result::Result<SearchFilters, String> {
+    let mut filters = SearchFilters::default();
+    let mut text = Vec::new();
+
+    for token in tokenize(input) {
+        let Some((key, value)) = token.split_once(':') else {
+            text.push(token);
+            continue;
+        };
+        let key = key.to_ascii_lowercase();
+        if !matches!(key.as_str(), "tag" | "category" | "location" | "before" | "after" | "platform") {
+            text.push(token);
+            continue;
+        }
+
+        let value = value.trim_matches('"').trim();
+        if value.is_empty() {
+            return Err(format!("\"{}:\" needs a value, e.g. {}", key, operator_example(&key)));
+        }
+        match key.as_str() {
+            "tag" => filters.tags.push(value.to_string()),
+            "category" => filters.categories.push(value.to_string()),
+            "location" => filters.location = Some(value.to_string()),
+            "before" => filters.before = Some(parse_date(&key, value)?),
+            "after" => filters.after = Some(parse_date(&key, value)?),
+            _ => {
+                if !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(format!("\"{}\" is not a platform name, e.g. {}", value, operator_example(&key)));
+                }
+                filters.platform = Some(value.to_ascii_uppercase());
+            }
+        }
+    }
+
+    if let (Some(after), Some(before)) = (filters.after, filters.before) {
+        if after >= before {
+            return Err("\"after:\" must be earlier than \"before:\"".to_string());
+        }
+    }
+    filters.text = text.join(" ");
+    Ok(filters)
+}
+
+/// Splits on whitespace, keeping double-quoted stretches (and their quotes) together.
+fn tokenize(input: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_quotes = false;
+    for (i, c) in input.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        if c.is_whitespace() && !in_quotes {
+            if let Some(s) = start.take() {
+                tokens.push(&input[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(&input[s..]);
+    }
+    tokens
+}
+
+fn parse_date(key: &str, value: &str) -> std::result::Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("\"{}\" is not a valid date, e.g. {}", value, operator_example(key)))
+}
+
+fn operator_example(key: &str) -> &'static str {
+    match key {
+        "tag" => "tag:diving",
+        "category" => "category:travel",
+        "location" => "location:cozumel",
+        "before" => "before:2024-01-01",
+        "after" => "after:2023-06-01",
+        _ => "platform:INSTAGRAM",
+    }
+}
+
+/// Escapes a `ts_headline` excerpt and turns its highlight markers into `<mark>` tags.
+fn highlight(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len() + 32);
+    let mut open = false;
+    for c in headline.chars() {
+        match c {
+            HIGHLIGHT_START if !open => {
+                html.push_str("<mark>");
+                open = true;
+            }
+            HIGHLIGHT_STOP if open => {
+                html.push_str("</mark>");
+                open = false;
+            }
+            HIGHLIGHT_START | HIGHLIGHT_STOP => {}
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#x27;"),
+            c => html.push(c),
+        }
+    }
+    if open {
+        html.push_str("</mark>");
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_values_together() {
+        assert_eq!(
+            tokenize(r#"  reef  location:"playa del carmen" tag:diving "#),
+            vec!["reef", r#"location:"playa del carmen""#, "tag:diving"],
+        );
+        // An unclosed quote runs to the end
+        assert_eq!(tokenize(r#"location:"la paz"#), vec![r#"location:"la paz"#]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn operators_and_text() {
+        let filters = parse_query(
+            r#"night dive TAG:turtles tag:reef category:travel location:"playa del carmen" after:2023-06-01 before:2024-01-01 platform:instagram"#,
+        )
+        .unwrap();
+        assert_eq!(filters, SearchFilters {
+            text: "night dive".to_string(),
+            tags: vec!["turtles".to_string(), "reef".to_string()],
+            categories: vec!["travel".to_string()],
+            location: Some("playa del carmen".to_string()),
+            before: Some(date("2024-01-01")),
+            after: Some(date("2023-06-01")),
+            platform: Some("INSTAGRAM".to_string()),
+        });
+    }
+
+    #[test]
+    fn empty_operator_values_are_errors() {
+        assert!(parse_query("tag:").unwrap_err().contains("tag:diving"));
+        assert!(parse_query(r#"location:"""#).is_err());
+    }
+
+    #[test]
+    fn bad_dates_are_errors() {
+        assert!(parse_query("before:2024-13-01").unwrap_err().contains("not a valid date"));
+        assert!(parse_query("after:yesterday").is_err());
+    }
+
+    #[test]
+    fn after_must_precede_before() {
+        assert!(parse_query("after:2024-01-01 before:2024-01-01").is_err());
+        assert!(parse_query("after:2024-02-01 before:2024-01-01").is_err());
+        assert!(parse_query("after:2023-12-31 before:2024-01-01").is_ok());
+    }
+
+    #[test]
+    fn unknown_operators_are_text() {
+        let filters = parse_query("foo:bar https://example.com reef").unwrap();
+        assert_eq!(filters.text, "foo:bar https://example.com reef");
+        assert!(filters.tags.is_empty());
+    }
+
+    #[test]
+    fn platform_names_are_validated() {
+        assert!(parse_query("platform:insta-gram").is_err());
+    }
+
+    #[test]
+    fn highlight_escapes_excerpt_html() {
+        let headline = format!("<script>alert('x')</script> & {}reef{} \"dive\"", HIGHLIGHT_START, HIGHLIGHT_STOP);
+        assert_eq!(
+            highlight(&headline),
+            "&lt;script&gt;alert(&#x27;x&#x27;)&lt;/script&gt; &amp; <mark>reef</mark> &quot;dive&quot;",
+        );
+    }
+
+    #[test]
+    fn highlight_balances_markers() {
+        // Unclosed highlight is closed at the end
+        assert_eq!(highlight(&format!("a {}reef", HIGHLIGHT_START)), "a <mark>reef</mark>");
+        // Stray stop and nested start markers are dropped
+        assert_eq!(highlight(&format!("a{} b", HIGHLIGHT_STOP)), "a b");
+        assert_eq!(
+            highlight(&format!("{}a{}b{}", HIGHLIGHT_START, HIGHLIGHT_START, HIGHLIGHT_STOP)),
+            "<mark>ab</mark>",
+        );
+    }
 }
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::models::{Category, Page, Post, Tag};
+use crate::models::post;
+
+/// The sitemap protocol's limit on URLs per file.
+const URLS_PER_SITEMAP: i64 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Posts,
+    Pages,
+    Tags,
+    Categories,
+}
+
+const SECTIONS: &[(&str, Section)] = &[
+    ("posts", Section::Posts),
+    ("pages", Section::Pages),
+    ("tags", Section::Tags),
+    ("categories", Section::Categories),
+];
+
+pub struct SitemapUrl {
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+#[derive(Template)]
+#[template(path = "sitemaps/index.xml")]
+pub struct SitemapIndexTemplate {
+    /// Absolute URLs of the child sitemaps
+    pub sitemaps: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "sitemaps/urlset.xml")]
+pub struct UrlSetTemplate {
+    pub urls: Vec<SitemapUrl>,
+}
+
+/// `/sitemap.xml`: index of the `/sitemaps/<section>-<n>.xml` files.
+pub async fn sitemap_index(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+) -> Result<Response> {
+    let mut sitemaps = Vec::new();
+    for (name, section) in SECTIONS {
+        let count = match section {
+            Section::Posts => Post::count_public(&pool).await?,
+            _ => section_urls(&pool, &config, *section).await?.len() as i64,
+        };
+        let chunks = (count + URLS_PER_SITEMAP - 1) / URLS_PER_SITEMAP;
+        for n in 1..=chunks {
+            sitemaps.push(format!("{}/sitemaps/{}-{}.xml", config.site_url, name, n));
+        }
+    }
+    xml_response(SitemapIndexTemplate { sitemaps }.render())
+}
+
+/// One chunk of a section, e.g. `/sitemaps/posts-2.xml`.
+pub async fn sitemap_section(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(file): Path<String>,
+) -> Result<Response> {
+    let not_found = || AppError::NotFound("Sitemap not found".to_string());
+    let (name, chunk) = file
+        .strip_suffix(".xml")
+        .and_then(|stem| stem.rsplit_once('-'))
+        .ok_or_else(not_found)?;
+    let section = SECTIONS.iter().find(|(n, _)| *n == name).map(|(_, s)| *s).ok_or_else(not_found)?;
+    let chunk: i64 = chunk.parse().ok().filter(|&n| n >= 1).ok_or_else(not_found)?;
+    let offset = chunk.checked_sub(1).and_then(|c| c.checked_mul(URLS_PER_SITEMAP)).ok_or_else(not_found)?;
+
+    let urls: Vec<SitemapUrl> = match section {
+        Section::Posts => Post::list_public_lastmod(&pool, URLS_PER_SITEMAP, offset).await?
+            .into_iter()
+            .map(|(id, title, body, updated_at)| SitemapUrl {
+                loc: format!("{}{}", config.site_url, post::url(id, title.as_deref(), &body)),
+                lastmod: Some(updated_at),
+            })
+            .collect(),
+        _ => section_urls(&pool, &config, section).await?
+            .into_iter()
+            .skip(offset as usize)
+            .take(URLS_PER_SITEMAP as usize)
+            .collect(),
+    };
+    if urls.is_empty() && chunk > 1 {
+        return Err(not_found());
+    }
+    xml_response(UrlSetTemplate { urls }.render())
+}
+
+/// `robots.txt` pointing crawlers at the sitemap and away from fragments and search.
+pub async fn robots_txt(State(config): State<Arc<Config>>) -> Response {
+    let body = format!(
+        "User-agent: *\nDisallow: /htmx/\nDisallow: /search/\n\nSitemap: {}/sitemap.xml\n",
+        config.site_url
+    );
+    (
+        [(CONTENT_TYPE, "text/plain; charset=utf-8"), (CACHE_CONTROL, "public, max-age=86400")],
+        body,
+    )
+        .into_response()
+}
+
+/// URLs of the small sections, which are loaded whole and chunked in memory.
+async fn section_urls(pool: &DbPool, config: &Config, section: Section) -> Result<Vec<SitemapUrl>> {
+    let urls = match section {
+        Section::Posts => vec![],
+        Section::Pages => {
+            let mut urls = vec![
+                SitemapUrl { loc: format!("{}/", config.site_url), lastmod: None },
+                SitemapUrl { loc: format!("{}/categories/", config.site_url), lastmod: None },
+                SitemapUrl { loc: format!("{}/tags/", config.site_url), lastmod: None },
+            ];
+            urls.extend(public_page_paths(Page::list_public(pool).await?).into_iter().map(|(path, updated_at)| {
+                SitemapUrl { loc: format!("{}{}", config.site_url, path), lastmod: Some(updated_at) }
+            }));
+            urls
+        }
+        Section::Tags => Tag::list_public_lastmod(pool).await?
+            .into_iter()
+            .map(|(slug, lastmod)| SitemapUrl {
+                loc: format!("{}/tags/{}/", config.site_url, slug),
+                lastmod: Some(lastmod),
+            })
+            .collect(),
+        Section::Categories => Category::list_public_lastmod(pool).await?
+            .into_iter()
+            .map(|(slug, lastmod)| SitemapUrl {
+                loc: format!("{}/category/{}/", config.site_url, slug),
+                lastmod: Some(lastmod),
+            })
+            .collect(),
+    };
+    Ok(urls)
+}
+
+/// `/parent/child/` paths of public pages; pages under a non-public parent are left out,
+/// since their URL can't be resolved by visitors either.
+fn public_page_paths(pages: Vec<Page>) -> Vec<(String, DateTime<Utc>)> {
+    let by_id: HashMap<i64, &Page> = pages.iter().map(|page| (page.id, page)).collect();
+    let mut paths = Vec::new();
+    'pages: for page in &pages {
+        let mut slugs = vec![page.slug.as_str()];
+        let mut parent_id = page.parent_id;
+        while let Some(id) = parent_id {
+            let Some(parent) = by_id.get(&id).filter(|_| slugs.len() < 32) else {
+                continue 'pages;
+            };
+            slugs.push(&parent.slug);
+            parent_id = parent.parent_id;
+        }
+        slugs.reverse();
+        paths.push((format!("/{}/", slugs.join("/")), page.updated_at));
+    }
+    paths
+}
+
+fn xml_response(rendered: askama::Result<String>) -> Result<Response> {
+    let body = rendered.map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok((
+        [(CONTENT_TYPE, "application/xml; charset=utf-8"), (CACHE_CONTROL, "public, max-age=3600")],
+        body,
+    )
+        .into_response())
+}
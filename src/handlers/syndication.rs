@@ -0,0 +1,263 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::http::Uri;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+use crate::handlers::feed::FeedScope;
+use crate::markdown;
+use crate::meta::{SITE_DESCRIPTION, SITE_NAME};
+use crate::models::{Category, PostMedia, Tag};
+use crate::privacy::Viewer;
+
+/// Entries per feed document.
+const FEED_LENGTH: i64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    /// Picks the format from the requested file name (`feed.xml`, `atom.xml`, `feed.json`).
+    fn from_path(path: &str) -> Option<Self> {
+        match path.rsplit('/').next()? {
+            "feed.xml" => Some(FeedFormat::Rss),
+            "atom.xml" => Some(FeedFormat::Atom),
+            "feed.json" => Some(FeedFormat::Json),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// A feed document independent of its format.
+pub struct Channel {
+    pub title: String,
+    pub description: String,
+    /// Absolute URL of the HTML listing the feed mirrors
+    pub html_url: String,
+    /// Absolute URL of this feed document
+    pub feed_url: String,
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Entry {
+    /// Absolute `/posts/:id/` URL, the entry's id; unlike `url` it survives title edits
+    pub id: String,
+    /// Absolute canonical post URL, with the slug
+    pub url: String,
+    pub title: String,
+    pub summary: String,
+    pub content_html: String,
+    pub published: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub tags: Vec<String>,
+    pub enclosure: Option<Enclosure>,
+}
+
+pub struct Enclosure {
+    pub url: String,
+    pub mime_type: String,
+    pub length: i64,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/rss.xml")]
+pub struct RssTemplate {
+    pub channel: Channel,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/atom.xml")]
+pub struct AtomTemplate {
+    pub channel: Channel,
+}
+
+/// JSON Feed 1.1 (https://www.jsonfeed.org/version/1.1/).
+#[derive(Serialize)]
+pub struct JsonFeed {
+    pub version: &'static str,
+    pub title: String,
+    pub home_page_url: String,
+    pub feed_url: String,
+    pub description: String,
+    pub items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+pub struct JsonFeedItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub summary: String,
+    pub content_html: String,
+    pub date_published: DateTime<Utc>,
+    pub date_modified: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Serialize)]
+pub struct JsonFeedAttachment {
+    pub url: String,
+    pub mime_type: String,
+    pub size_in_bytes: i64,
+}
+
+/// `/feed.xml`, `/atom.xml` and `/feed.json`: the latest public posts.
+pub async fn site_feed(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    uri: Uri,
+) -> Result<Response> {
+    let format = FeedFormat::from_path(uri.path()).ok_or_else(feed_not_found)?;
+    let channel = build_channel(&pool, &config, &FeedScope::All, SITE_NAME.to_string(), "/", uri.path()).await?;
+    render(format, channel)
+}
+
+pub async fn tag_feed(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(slug): Path<String>,
+    uri: Uri,
+) -> Result<Response> {
+    let format = FeedFormat::from_path(uri.path()).ok_or_else(feed_not_found)?;
+    let tag = Tag::find_by_slug(&pool, &slug).await?.ok_or_else(feed_not_found)?;
+    let title = format!("{}: {}", SITE_NAME, tag.name);
+    let html_path = format!("/tags/{}/", tag.slug);
+    let channel = build_channel(&pool, &config, &FeedScope::Tag(tag), title, &html_path, uri.path()).await?;
+    render(format, channel)
+}
+
+pub async fn category_feed(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    Path(slug): Path<String>,
+    uri: Uri,
+) -> Result<Response> {
+    let format = FeedFormat::from_path(uri.path()).ok_or_else(feed_not_found)?;
+    let category = Category::find_by_slug(&pool, &slug).await?.ok_or_else(feed_not_found)?;
+    let title = format!("{}: {}", SITE_NAME, category.name);
+    let html_path = format!("/category/{}/", category.slug);
+    let channel = build_channel(&pool, &config, &FeedScope::Category(category), title, &html_path, uri.path()).await?;
+    render(format, channel)
+}
+
+fn feed_not_found() -> AppError {
+    AppError::NotFound("Feed not found".to_string())
+}
+
+/// Latest posts of a listing as seen by an anonymous visitor, so feeds (which get cached
+/// and shared) only ever contain `PUBLIC` posts whatever cookies the request carried.
+async fn build_channel(
+    pool: &DbPool,
+    config: &Config,
+    scope: &FeedScope,
+    title: String,
+    html_path: &str,
+    feed_path: &str,
+) -> Result<Channel> {
+    let viewer = Viewer::new(None);
+    let posts = scope.list_posts(pool, &viewer, FEED_LENGTH, 0).await?;
+
+    let mut entries = Vec::new();
+    for post in posts {
+        let enclosure = PostMedia::get_featured_for_post(pool, post.id).await?.map(|media| Enclosure {
+            url: format!("{}{}", config.site_url, media.url()),
+            mime_type: media.mime_type.clone().unwrap_or_else(|| "application/octet-stream".to_string()),
+            length: media.file_size,
+        });
+        let tags = Tag::list_for_post(pool, post.id).await?.into_iter().map(|t| t.name).collect();
+        entries.push(Entry {
+            id: format!("{}/posts/{}/", config.site_url, post.id),
+            url: format!("{}{}", config.site_url, post.url()),
+            title: post.title.clone().unwrap_or_else(|| untitled(&post.body)),
+            summary: post.preview(),
+            content_html: markdown::render_cached("post", post.id, post.updated_at, &post.body),
+            published: post.created_at,
+            updated: post.updated_at,
+            tags,
+            enclosure,
+        });
+    }
+
+    Ok(Channel {
+        title,
+        description: SITE_DESCRIPTION.to_string(),
+        html_url: format!("{}{}", config.site_url, html_path),
+        feed_url: format!("{}{}", config.site_url, feed_path),
+        updated: entries.iter().map(|e| e.updated).max().unwrap_or_else(Utc::now),
+        entries,
+    })
+}
+
+/// Feed readers need a title; untitled posts use the start of their text.
+fn untitled(body: &str) -> String {
+    let text = markdown::plain_text(body);
+    match text.char_indices().nth(80) {
+        Some((cut, _)) => format!("{}…", text[..cut].trim_end()),
+        None => text,
+    }
+}
+
+fn render(format: FeedFormat, channel: Channel) -> Result<Response> {
+    let body = match format {
+        FeedFormat::Rss => RssTemplate { channel }.render().map_err(|e| AppError::Internal(e.to_string()))?,
+        FeedFormat::Atom => AtomTemplate { channel }.render().map_err(|e| AppError::Internal(e.to_string()))?,
+        FeedFormat::Json => serde_json::to_string(&json_feed(channel)).map_err(|e| AppError::Internal(e.to_string()))?,
+    };
+    Ok((
+        [(CONTENT_TYPE, format.content_type()), (CACHE_CONTROL, "public, max-age=900")],
+        body,
+    )
+        .into_response())
+}
+
+fn json_feed(channel: Channel) -> JsonFeed {
+    JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: channel.title,
+        home_page_url: channel.html_url,
+        feed_url: channel.feed_url,
+        description: channel.description,
+        items: channel
+            .entries
+            .into_iter()
+            .map(|entry| JsonFeedItem {
+                id: entry.id,
+                url: entry.url,
+                title: entry.title,
+                summary: entry.summary,
+                content_html: entry.content_html,
+                date_published: entry.published,
+                date_modified: entry.updated,
+                tags: entry.tags,
+                attachments: entry
+                    .enclosure
+                    .into_iter()
+                    .map(|e| JsonFeedAttachment { url: e.url, mime_type: e.mime_type, size_in_bytes: e.length })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use askama::Template;
+use askama_axum::IntoResponse;
+use axum::extract::State;
+
+use crate::auth::CurrentUser;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::Result;
+use crate::models::{NavPage, Page, Post, Profile, Tag};
+use crate::meta::PageMeta;
+use crate::privacy::Viewer;
+
+pub struct TagCount {
+    pub tag: Tag,
+    /// Visible posts carrying the tag
+    pub post_count: i64,
+    /// Cloud font size step, 1 (rarest) to 5 (most used)
+    pub weight: i64,
+}
+
+#[derive(Template)]
+#[template(path = "tags.html")]
+pub struct TagsTemplate {
+    pub tags: Vec<TagCount>,
+    pub nav_pages: Vec<NavPage>,
+    pub current_path: String,
+    pub meta: PageMeta,
+    pub user: Option<Profile>,
+}
+
+#[derive(Template)]
+#[template(path = "partials/tag_cloud.html")]
+pub struct TagCloudTemplate {
+    pub tags: Vec<TagCount>,
+}
+
+/// Most used tags shown in the sidebar cloud.
+const CLOUD_SIZE: usize = 30;
+
+pub async fn tag_index(
+    State(pool): State<DbPool>,
+    State(config): State<Arc<Config>>,
+    CurrentUser(user): CurrentUser,
+) -> Result<impl IntoResponse> {
+    let viewer = Viewer::new(user.as_ref());
+    let tags = tag_counts(&pool, &viewer).await?;
+    let nav_pages = Page::list_nav_pages(&pool).await?;
+
+    Ok(TagsTemplate {
+        tags,
+        nav_pages,
+        current_path: "/tags/".to_string(),
+        meta: PageMeta::new(&config, "/tags/", "Tags", "Every tag and how many posts carry it"),
+        user,
+    })
+}
+
+/// Sidebar tag cloud, loaded by htmx so every page using the sidebar doesn't need the counts.
+pub async fn tag_cloud(
+    State(pool): State<DbPool>,
+    CurrentUser(user): CurrentUser,
+) -> Result<impl IntoResponse> {
+    let viewer = Viewer::new(user.as_ref());
+    let mut tags = tag_counts(&pool, &viewer).await?;
+
+    tags.sort_by_key(|t| std::cmp::Reverse(t.post_count));
+    tags.truncate(CLOUD_SIZE);
+    tags.sort_by(|a, b| a.tag.name.cmp(&b.tag.name));
+    Ok(TagCloudTemplate { tags })
+}
+
+/// Active tags with at least one visible post, alphabetically. Tags only used on posts
+/// the viewer can't see are left out so their names aren't revealed.
+async fn tag_counts(pool: &DbPool, viewer: &Viewer<'_>) -> Result<Vec<TagCount>> {
+    let counts = Post::count_by_tag(pool, viewer).await?;
+    let max = counts.values().copied().max().unwrap_or(1);
+
+    Ok(Tag::list_all(pool).await?
+        .into_iter()
+        .filter_map(|tag| {
+            let post_count = counts.get(&tag.id).copied().filter(|&n| n > 0)?;
+            Some(TagCount { weight: weight(post_count, max), tag, post_count })
+        })
+        .collect())
+}
+
+/// Logarithmic scale so a few very common tags don't flatten the rest of the cloud.
+fn weight(count: i64, max: i64) -> i64 {
+    if max <= 1 {
+        return 3;
+    }
+    let ratio = (count as f64).ln() / (max as f64).ln();
+    1 + (ratio * 4.0).round() as i64
+}
@@ -1,14 +1,27 @@
+mod auth;
+mod cards;
+mod config;
 mod db;
 mod error;
 mod handlers;
+mod markdown;
+mod meta;
 mod models;
 mod privacy;
+mod share;
+mod state;
 
-use axum::Router;
+use axum::extract::Request;
+use axum::{middleware, Router};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::config::Config;
+use crate::state::AppState;
+
 #[tokio::main]
 async fn main() {
     // Initialize tracing
@@ -32,11 +45,31 @@ async fn main() {
 
     tracing::info!("Connected to PostgreSQL database");
 
+    if !privacy::detect_custom_audiences(&pool).await.expect("Failed to inspect database schema") {
+        tracing::warn!("{} not found; CUSTOM posts are visible to admins only", privacy::CUSTOM_VIEWERS_TABLE);
+    }
+
+    let state = AppState {
+        pool,
+        config: Arc::new(Config::from_env()),
+    };
+
     // Build router
     let app = Router::new()
         .merge(handlers::public_routes())
-        .layer(TraceLayer::new_for_http())
-        .with_state(pool);
+        .layer(middleware::from_fn_with_state(state.clone(), handlers::errors::render_error_page))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(|request: &Request| {
+            // Request id in every log line, matching the one shown on error pages
+            let request_id = request
+                .headers()
+                .get(handlers::errors::X_REQUEST_ID)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            tracing::info_span!("request", method = %request.method(), uri = %request.uri(), request_id)
+        }))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
 
     // Run server
     let addr = SocketAddr::from(([0, 0, 0, 0], 3002));
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, Utc};
+use linkify::{LinkFinder, LinkKind};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+/// Rendered bodies, keyed by kind and id and valid while `updated_at` matches, so an edit
+/// in Django invalidates the entry without any coordination.
+type RenderCache = HashMap<(&'static str, i64), (DateTime<Utc>, String)>;
+
+static CACHE: LazyLock<Mutex<RenderCache>> = LazyLock::new(Default::default);
+
+/// Upper bound on cached bodies; the cache is simply emptied when it fills up.
+const CACHE_CAPACITY: usize = 2000;
+
+/// Length of plain-text previews on feed cards, in characters.
+pub const PREVIEW_LENGTH: usize = 280;
+
+/// Markdown to sanitized HTML: tables, strikethrough and task lists are enabled, bare URLs
+/// become links, and anything outside ammonia's allowlist (scripts, inline event handlers,
+/// `javascript:` URLs, ...) is stripped.
+pub fn render(markdown: &str) -> String {
+    let mut events = Vec::new();
+    let mut in_link_or_code = 0usize;
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::Link { .. } | Tag::Image { .. } | Tag::CodeBlock(_)) => {
+                in_link_or_code += 1;
+                events.push(event);
+            }
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::CodeBlock) => {
+                in_link_or_code = in_link_or_code.saturating_sub(1);
+                events.push(event);
+            }
+            Event::Text(text) if in_link_or_code == 0 => autolink(text, &mut events),
+            event => events.push(event),
+        }
+    }
+
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, events.into_iter());
+    ammonia::Builder::default()
+        .link_rel(Some("nofollow noopener noreferrer"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+/// `render`, reusing the previous output for the same object until `updated_at` changes.
+pub fn render_cached(kind: &'static str, id: i64, updated_at: DateTime<Utc>, markdown: &str) -> String {
+    let key = (kind, id);
+    if let Some((stamp, html)) = CACHE.lock().unwrap().get(&key) {
+        if *stamp == updated_at {
+            return html.clone();
+        }
+    }
+
+    let html = render(markdown);
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, (updated_at, html.clone()));
+    html
+}
+
+/// The text of a Markdown document without markup, e.g. for previews and feed summaries.
+pub fn plain_text(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item)
+                if !text.ends_with(char::is_whitespace) =>
+            {
+                text.push(' ');
+            }
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Plain-text preview cut to `PREVIEW_LENGTH` characters (never mid-character).
+pub fn preview(markdown: &str) -> String {
+    let text = plain_text(markdown);
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((cut, _)) => format!("{}...", text[..cut].trim_end()),
+        None => text,
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+/// Splits a text event around bare URLs and email addresses, wrapping those in links.
+fn autolink<'a>(text: CowStr<'a>, events: &mut Vec<Event<'a>>) {
+    let finder = LinkFinder::new();
+    let mut links = finder.links(&text).peekable();
+    if links.peek().is_none() {
+        events.push(Event::Text(text));
+        return;
+    }
+
+    let mut last = 0;
+    for link in links {
+        if link.start() > last {
+            events.push(Event::Text(CowStr::from(text[last..link.start()].to_string())));
+        }
+        let href = match link.kind() {
+            LinkKind::Email => format!("mailto:{}", link.as_str()),
+            _ => link.as_str().to_string(),
+        };
+        events.push(Event::Start(Tag::Link {
+            link_type: pulldown_cmark::LinkType::Autolink,
+            dest_url: CowStr::from(href),
+            title: CowStr::from(""),
+            id: CowStr::from(""),
+        }));
+        events.push(Event::Text(CowStr::from(link.as_str().to_string())));
+        events.push(Event::End(TagEnd::Link));
+        last = link.end();
+    }
+    if last < text.len() {
+        events.push(Event::Text(CowStr::from(text[last..].to_string())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_strips_scripts_handlers_and_javascript_urls() {
+        let html = render("<script>alert(1)</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">\n\n[link](javascript:alert(1))");
+        assert!(!html.contains("<script"), "{}", html);
+        assert!(!html.contains("onerror"), "{}", html);
+        assert!(!html.contains("javascript:"), "{}", html);
+        assert!(html.contains("<img src=\"x.png\""), "{}", html);
+    }
+
+    #[test]
+    fn render_links_bare_urls_and_emails() {
+        let html = render("See https://example.com/a?b=1 or mail me@example.com.");
+        assert!(html.contains("<a href=\"https://example.com/a?b=1\" rel=\"nofollow noopener noreferrer\">https://example.com/a?b=1</a>"), "{}", html);
+        assert!(html.contains("<a href=\"mailto:me@example.com\" rel=\"nofollow noopener noreferrer\">me@example.com</a>."), "{}", html);
+    }
+
+    #[test]
+    fn render_leaves_code_and_existing_links_alone() {
+        let html = render("```\ncurl https://example.com/block\n```\n\n`https://example.com/inline`");
+        assert!(!html.contains("<a"), "{}", html);
+
+        let html = render("[https://example.com/text](https://example.com/target)");
+        assert_eq!(html.matches("<a ").count(), 1, "{}", html);
+        assert!(html.contains("href=\"https://example.com/target\""), "{}", html);
+    }
+
+    #[test]
+    fn plain_text_drops_markup() {
+        let text = plain_text("# Title\n\nSome **bold** and `code`, a [link](https://example.com).\n\n- one\n- two");
+        assert_eq!(text, "Title Some bold and code, a link. one two");
+    }
+
+    #[test]
+    fn preview_cuts_on_a_char_boundary() {
+        assert_eq!(preview("Short *post*"), "Short post");
+
+        let long = "é".repeat(PREVIEW_LENGTH + 10);
+        let cut = preview(&long);
+        assert!(cut.ends_with("..."));
+        assert_eq!(cut.trim_end_matches("...").chars().count(), PREVIEW_LENGTH);
+        assert!(cut.chars().all(|c| c == 'é' || c == '.'));
+    }
+}
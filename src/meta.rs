@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::cards;
+use crate::config::Config;
+use crate::models::media::PostMediaWithItem;
+
+pub const SITE_NAME: &str = "Nestor Wheelock";
+pub const SITE_DESCRIPTION: &str = "Diver · Traveler · Tech Nerd";
+
+/// Social and search metadata for a rendered page, emitted by `base.html` as OpenGraph,
+/// Twitter Card and schema.org JSON-LD.
+#[derive(Debug, Clone)]
+pub struct PageMeta {
+    pub title: String,
+    pub description: String,
+    /// Absolute URL without query string
+    pub canonical_url: String,
+    pub image: Option<MetaImage>,
+    /// Set for posts, which are described as articles / `BlogPosting`s
+    pub published: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetaImage {
+    pub url: String,
+    pub alt: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl PageMeta {
+    /// Metadata for a plain web page at `path`.
+    pub fn new(config: &Config, path: &str, title: impl Into<String>, description: impl Into<String>) -> Self {
+        PageMeta {
+            title: title.into(),
+            description: description.into(),
+            canonical_url: format!("{}{}", config.site_url, path),
+            image: None,
+            published: None,
+            modified: None,
+        }
+    }
+
+    /// Describes the page as an article published and last modified at the given times.
+    pub fn article(mut self, published: DateTime<Utc>, modified: DateTime<Utc>) -> Self {
+        self.published = Some(published);
+        self.modified = Some(modified);
+        self
+    }
+
+    /// Uses a post's media as the share image; ignored for anything but images.
+    pub fn with_image(mut self, config: &Config, media: Option<&PostMediaWithItem>) -> Self {
+        if let Some(media) = media.filter(|m| m.media_type.eq_ignore_ascii_case("image")) {
+            self.image = Some(MetaImage {
+                url: format!("{}{}", config.site_url, media.url()),
+                alt: if media.custom_alt_text.is_empty() {
+                    self.title.clone()
+                } else {
+                    media.custom_alt_text.clone()
+                },
+                width: media.width,
+                height: media.height,
+            });
+        }
+        self
+    }
+
+    /// Falls back to the generated share card at `url` when there's no other image.
+    pub fn with_card(mut self, url: String) -> Self {
+        if self.image.is_none() {
+            self.image = Some(MetaImage {
+                url,
+                alt: self.title.clone(),
+                width: Some(cards::WIDTH as i32),
+                height: Some(cards::HEIGHT as i32),
+            });
+        }
+        self
+    }
+
+    pub fn og_type(&self) -> &'static str {
+        if self.published.is_some() { "article" } else { "website" }
+    }
+
+    pub fn twitter_card(&self) -> &'static str {
+        if self.image.is_some() { "summary_large_image" } else { "summary" }
+    }
+
+    /// schema.org `BlogPosting` (posts) or `WebPage` JSON-LD, safe to embed in a `<script>`.
+    pub fn json_ld(&self) -> String {
+        let mut data = json!({
+            "@context": "https://schema.org",
+            "@type": if self.published.is_some() { "BlogPosting" } else { "WebPage" },
+            "headline": self.title,
+            "name": self.title,
+            "description": self.description,
+            "url": self.canonical_url,
+            "mainEntityOfPage": self.canonical_url,
+            "author": { "@type": "Person", "name": SITE_NAME },
+        });
+        if let Some(published) = self.published {
+            data["datePublished"] = json!(published.to_rfc3339());
+        }
+        if let Some(modified) = self.modified {
+            data["dateModified"] = json!(modified.to_rfc3339());
+        }
+        if let Some(ref image) = self.image {
+            data["image"] = json!({
+                "@type": "ImageObject",
+                "url": image.url,
+                "width": image.width,
+                "height": image.height,
+            });
+        }
+        // `<` can't appear raw, so the text can never close the surrounding script tag
+        data.to_string().replace('<', "\\u003c")
+    }
+}
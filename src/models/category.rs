@@ -61,4 +61,50 @@ impl Category {
         .await?;
         Ok(categories)
     }
+
+    /// Slugs of active categories with `PUBLIC` posts in their subtree (as listed on the
+    /// category page), with the latest `updated_at` among those posts.
+    pub async fn list_public_lastmod(pool: &PgPool) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let categories = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            "WITH RECURSIVE subtree AS (
+                 SELECT id AS root_id, id FROM posts_category WHERE is_active = true
+                 UNION
+                 SELECT s.root_id, c.id FROM posts_category c
+                 JOIN subtree s ON c.parent_id = s.id
+                 WHERE c.is_active = true
+             )
+             SELECT r.slug, MAX(p.updated_at)
+             FROM subtree s
+             JOIN posts_category r ON r.id = s.root_id
+             JOIN posts_post p ON p.category_id = s.id
+             WHERE p.is_draft = false AND p.is_deleted = false AND p.visibility = 'PUBLIC'
+             GROUP BY r.slug
+             ORDER BY r.slug"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(categories)
+    }
+
+    /// Active ancestors of a category, root first, for breadcrumbs.
+    pub async fn ancestors(pool: &PgPool, id: i64) -> Result<Vec<Self>> {
+        let categories = sqlx::query_as::<_, Category>(
+            "WITH RECURSIVE ancestors AS (
+                 SELECT p.*, 1 AS depth FROM posts_category c
+                 JOIN posts_category p ON p.id = c.parent_id
+                 WHERE c.id = $1
+                 UNION ALL
+                 SELECT p.*, a.depth + 1 FROM posts_category p
+                 JOIN ancestors a ON p.id = a.parent_id
+                 WHERE a.depth < 32
+             )
+             SELECT id, name, slug, description, parent_id, display_order, is_active, created_at, updated_at
+             FROM ancestors WHERE is_active = true
+             ORDER BY depth DESC"
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(categories)
+    }
 }
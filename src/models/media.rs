@@ -41,6 +41,8 @@ pub struct PostMediaWithItem {
     pub original_filename: String,
     pub width: Option<i32>,
     pub height: Option<i32>,
+    pub mime_type: Option<String>,
+    pub file_size: i64,
 }
 
 impl MediaLibrary {
@@ -56,6 +58,18 @@ impl MediaLibrary {
         Ok(media)
     }
 
+    pub async fn find_by_file(pool: &PgPool, file: &str) -> Result<Option<Self>> {
+        let media = sqlx::query_as::<_, MediaLibrary>(
+            "SELECT id, file, content_hash, media_type, original_filename, file_size,
+                    width, height, mime_type, uploaded_by_id, created_at
+             FROM posts_medialibrary WHERE file = $1"
+        )
+        .bind(file)
+        .fetch_optional(pool)
+        .await?;
+        Ok(media)
+    }
+
     pub fn url(&self) -> String {
         format!("/media/{}", self.file)
     }
@@ -65,7 +79,8 @@ impl PostMedia {
     pub async fn list_for_post(pool: &PgPool, post_id: i64) -> Result<Vec<PostMediaWithItem>> {
         let media = sqlx::query_as::<_, PostMediaWithItem>(
             r#"SELECT pm.id, pm.post_id, pm.order, pm.custom_alt_text,
-                      ml.file, ml.media_type, ml.original_filename, ml.width, ml.height
+                      ml.file, ml.media_type, ml.original_filename, ml.width, ml.height,
+                      ml.mime_type, ml.file_size
                FROM posts_postmedia pm
                JOIN posts_medialibrary ml ON pm.library_item_id = ml.id
                WHERE pm.post_id = $1
@@ -80,7 +95,8 @@ impl PostMedia {
     pub async fn get_featured_for_post(pool: &PgPool, post_id: i64) -> Result<Option<PostMediaWithItem>> {
         let media = sqlx::query_as::<_, PostMediaWithItem>(
             r#"SELECT pm.id, pm.post_id, pm.order, pm.custom_alt_text,
-                      ml.file, ml.media_type, ml.original_filename, ml.width, ml.height
+                      ml.file, ml.media_type, ml.original_filename, ml.width, ml.height,
+                      ml.mime_type, ml.file_size
                FROM posts_postmedia pm
                JOIN posts_medialibrary ml ON pm.library_item_id = ml.id
                WHERE pm.post_id = $1
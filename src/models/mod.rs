@@ -5,11 +5,18 @@ pub mod page;
 pub mod media;
 pub mod contact;
 pub mod profile;
+pub mod search;
+pub mod session;
+pub mod share_link;
+pub mod slug_redirect;
 
 pub use post::Post;
 pub use category::Category;
 pub use tag::Tag;
-pub use page::Page;
+pub use page::{NavPage, Page};
 pub use media::{MediaLibrary, PostMedia};
 pub use contact::{ContactSubmission, CreateContactSubmission};
 pub use profile::Profile;
+pub use session::{DjangoSession, DjangoUser};
+pub use share_link::RevokedShareLink;
+pub use slug_redirect::SlugRedirect;
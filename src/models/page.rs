@@ -24,31 +24,159 @@ pub struct Page {
     pub updated_at: DateTime<Utc>,
 }
 
+/// A sidebar navigation entry: a top-level page and the child pages listed under it.
+#[derive(Debug, Clone)]
+pub struct NavPage {
+    pub page: Page,
+    pub children: Vec<Page>,
+}
+
 impl Page {
-    pub async fn find_by_slug(pool: &PgPool, slug: &str) -> Result<Option<Self>> {
+    pub async fn find_by_id(pool: &PgPool, id: i64) -> Result<Option<Self>> {
+        let page = sqlx::query_as::<_, Page>(
+            "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM posts_page WHERE id = $1 AND is_published = true"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(page)
+    }
+
+    /// Like `find_by_id`, but includes unpublished pages (for admin previews).
+    pub async fn find_for_preview(pool: &PgPool, id: i64) -> Result<Option<Self>> {
         let page = sqlx::query_as::<_, Page>(
             "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
                     show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
                     is_published, visibility, author_id, created_at, updated_at
-             FROM posts_page WHERE slug = $1 AND is_published = true"
+             FROM posts_page WHERE id = $1"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(page)
+    }
+
+    /// Published child pages with `slug`, under any parent. Slugs are only unique among
+    /// siblings, so there can be several.
+    pub async fn list_nested_by_slug(pool: &PgPool, slug: &str) -> Result<Vec<Self>> {
+        let pages = sqlx::query_as::<_, Page>(
+            "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM posts_page
+             WHERE slug = $1 AND parent_id IS NOT NULL AND is_published = true
+             ORDER BY id"
         )
         .bind(slug)
+        .fetch_all(pool)
+        .await?;
+        Ok(pages)
+    }
+
+    /// Top-level navigation pages, each with its navigation children for sidebar sub-items.
+    pub async fn list_nav_pages(pool: &PgPool) -> Result<Vec<NavPage>> {
+        let pages = sqlx::query_as::<_, Page>(
+            "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM posts_page
+             WHERE is_published = true AND show_in_nav = true AND visibility = 'PUBLIC'
+                   AND (parent_id IS NULL OR parent_id IN (
+                       SELECT id FROM posts_page
+                       WHERE is_published = true AND show_in_nav = true AND visibility = 'PUBLIC'
+                             AND parent_id IS NULL
+                   ))
+             ORDER BY display_order, title"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let (top_level, children): (Vec<Page>, Vec<Page>) =
+            pages.into_iter().partition(|page| page.parent_id.is_none());
+        Ok(top_level
+            .into_iter()
+            .map(|page| NavPage {
+                children: children.iter().filter(|c| c.parent_id == Some(page.id)).cloned().collect(),
+                page,
+            })
+            .collect())
+    }
+
+    /// Published `PUBLIC` pages, for the sitemap.
+    pub async fn list_public(pool: &PgPool) -> Result<Vec<Self>> {
+        let pages = sqlx::query_as::<_, Page>(
+            "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM posts_page
+             WHERE is_published = true AND visibility = 'PUBLIC'
+             ORDER BY id"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(pages)
+    }
+
+    /// Published page with `slug` directly under `parent_id` (`None` for top-level pages).
+    pub async fn find_child(pool: &PgPool, parent_id: Option<i64>, slug: &str) -> Result<Option<Self>> {
+        let page = sqlx::query_as::<_, Page>(
+            "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM posts_page
+             WHERE parent_id IS NOT DISTINCT FROM $1 AND slug = $2 AND is_published = true"
+        )
+        .bind(parent_id)
+        .bind(slug)
         .fetch_optional(pool)
         .await?;
         Ok(page)
     }
 
-    pub async fn list_nav_pages(pool: &PgPool) -> Result<Vec<Self>> {
+    pub async fn list_children(pool: &PgPool, parent_id: i64) -> Result<Vec<Self>> {
         let pages = sqlx::query_as::<_, Page>(
             "SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
                     show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
                     is_published, visibility, author_id, created_at, updated_at
              FROM posts_page
-             WHERE is_published = true AND show_in_nav = true AND visibility = 'PUBLIC' AND parent_id IS NULL
+             WHERE parent_id = $1 AND is_published = true
              ORDER BY display_order, title"
         )
+        .bind(parent_id)
         .fetch_all(pool)
         .await?;
         Ok(pages)
     }
+
+    /// Ancestors of a page, root first, for breadcrumbs and building its URL.
+    pub async fn ancestors(pool: &PgPool, id: i64) -> Result<Vec<Self>> {
+        let pages = sqlx::query_as::<_, Page>(
+            "WITH RECURSIVE ancestors AS (
+                 SELECT p.*, 1 AS depth FROM posts_page c
+                 JOIN posts_page p ON p.id = c.parent_id
+                 WHERE c.id = $1
+                 UNION ALL
+                 SELECT p.*, a.depth + 1 FROM posts_page p
+                 JOIN ancestors a ON p.id = a.parent_id
+                 WHERE a.depth < 32
+             )
+             SELECT id, title, slug, body, parent_id, template, show_in_nav, display_order,
+                    show_posts_from_category_id, show_posts_with_tag_id, posts_per_page,
+                    is_published, visibility, author_id, created_at, updated_at
+             FROM ancestors
+             ORDER BY depth DESC"
+        )
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+        Ok(pages)
+    }
+
+    /// Whether the page lists posts from a category or tag below its body.
+    pub fn lists_posts(&self) -> bool {
+        self.show_posts_from_category_id.is_some() || self.show_posts_with_tag_id.is_some()
+    }
 }
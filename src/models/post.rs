@@ -1,9 +1,13 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
 use crate::error::Result;
-use crate::privacy::allowed_visibilities;
+use crate::markdown;
+use crate::models::Page;
+use crate::privacy::{self, post_filter_sql, Viewer};
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Post {
@@ -40,18 +44,25 @@ impl Post {
         Ok(post)
     }
 
-    pub async fn list_public(pool: &PgPool, user_tier: Option<&str>, limit: i64, offset: i64) -> Result<Vec<Self>> {
-        let visibilities = allowed_visibilities(user_tier);
-        let posts = sqlx::query_as::<_, Post>(
+    /// Like `find_by_id`, but includes drafts (for admin previews).
+    pub async fn find_for_preview(pool: &PgPool, id: i64) -> Result<Option<Self>> {
+        let post = sqlx::query_as::<_, Post>(
             "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
                     is_archived, is_deleted, category_id, source_platform, like_count,
                     comment_count, share_count, created_at, updated_at
-             FROM posts_post
-             WHERE is_draft = false AND is_deleted = false AND visibility = ANY($1)
-             ORDER BY is_pinned DESC, created_at DESC
-             LIMIT $2 OFFSET $3"
+             FROM posts_post WHERE id = $1 AND is_deleted = false"
         )
-        .bind(&visibilities)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(post)
+    }
+
+    pub async fn list_public(pool: &PgPool, viewer: &Viewer<'_>, limit: i64, offset: i64) -> Result<Vec<Self>> {
+        let sql = list_public_sql();
+        let posts = sqlx::query_as::<_, Post>(&sql)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
@@ -59,19 +70,13 @@ impl Post {
         Ok(posts)
     }
 
-    pub async fn list_by_category(pool: &PgPool, category_id: i64, user_tier: Option<&str>, limit: i64, offset: i64) -> Result<Vec<Self>> {
-        let visibilities = allowed_visibilities(user_tier);
-        let posts = sqlx::query_as::<_, Post>(
-            "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
-                    is_archived, is_deleted, category_id, source_platform, like_count,
-                    comment_count, share_count, created_at, updated_at
-             FROM posts_post
-             WHERE is_draft = false AND is_deleted = false AND category_id = $1 AND visibility = ANY($2)
-             ORDER BY is_pinned DESC, created_at DESC
-             LIMIT $3 OFFSET $4"
-        )
+    /// Posts filed under the category or any of its descendants.
+    pub async fn list_by_category(pool: &PgPool, category_id: i64, viewer: &Viewer<'_>, limit: i64, offset: i64) -> Result<Vec<Self>> {
+        let sql = list_by_category_sql();
+        let posts = sqlx::query_as::<_, Post>(&sql)
         .bind(category_id)
-        .bind(&visibilities)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
@@ -79,20 +84,12 @@ impl Post {
         Ok(posts)
     }
 
-    pub async fn list_by_tag(pool: &PgPool, tag_id: i64, user_tier: Option<&str>, limit: i64, offset: i64) -> Result<Vec<Self>> {
-        let visibilities = allowed_visibilities(user_tier);
-        let posts = sqlx::query_as::<_, Post>(
-            "SELECT p.id, p.title, p.body, p.location, p.author_id, p.visibility, p.is_draft,
-                    p.is_pinned, p.is_archived, p.is_deleted, p.category_id, p.source_platform,
-                    p.like_count, p.comment_count, p.share_count, p.created_at, p.updated_at
-             FROM posts_post p
-             JOIN posts_post_tags pt ON p.id = pt.post_id
-             WHERE p.is_draft = false AND p.is_deleted = false AND pt.tag_id = $1 AND p.visibility = ANY($2)
-             ORDER BY p.is_pinned DESC, p.created_at DESC
-             LIMIT $3 OFFSET $4"
-        )
+    pub async fn list_by_tag(pool: &PgPool, tag_id: i64, viewer: &Viewer<'_>, limit: i64, offset: i64) -> Result<Vec<Self>> {
+        let sql = list_by_tag_sql();
+        let posts = sqlx::query_as::<_, Post>(&sql)
         .bind(tag_id)
-        .bind(&visibilities)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
@@ -100,21 +97,61 @@ impl Post {
         Ok(posts)
     }
 
-    pub async fn search(pool: &PgPool, query: &str, user_tier: Option<&str>, limit: i64, offset: i64) -> Result<Vec<Self>> {
-        let visibilities = allowed_visibilities(user_tier);
-        let search_pattern = format!("%{}%", query);
-        let posts = sqlx::query_as::<_, Post>(
-            "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
-                    is_archived, is_deleted, category_id, source_platform, like_count,
-                    comment_count, share_count, created_at, updated_at
+    /// Posts shown on a section page: those in its category (subcategories included)
+    /// and/or carrying its tag, whichever of the two the page sets.
+    pub async fn list_for_page(pool: &PgPool, page: &Page, viewer: &Viewer<'_>, limit: i64, offset: i64) -> Result<Vec<Self>> {
+        let sql = list_for_page_sql();
+        let posts = sqlx::query_as::<_, Post>(&sql)
+        .bind(page.show_posts_from_category_id)
+        .bind(page.show_posts_with_tag_id)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+        Ok(posts)
+    }
+
+    /// Number of visible posts filed directly under each category, keyed by category id.
+    pub async fn count_by_category(pool: &PgPool, viewer: &Viewer<'_>) -> Result<HashMap<i64, i64>> {
+        let sql = count_by_category_sql();
+        let counts = sqlx::query_as::<_, (i64, i64)>(&sql)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Number of visible posts carrying each tag, keyed by tag id.
+    pub async fn count_by_tag(pool: &PgPool, viewer: &Viewer<'_>) -> Result<HashMap<i64, i64>> {
+        let sql = count_by_tag_sql();
+        let counts = sqlx::query_as::<_, (i64, i64)>(&sql)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(counts.into_iter().collect())
+    }
+
+    /// Ids and `updated_at` of `PUBLIC` posts for the sitemap, oldest first so chunks stay stable,
+    /// with the title and, for untitled posts only, the body to derive the slug. The whole body
+    /// is needed: markup can hide any amount of text before the words the slug is made of.
+    pub async fn list_public_lastmod(
+        pool: &PgPool,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<(i64, Option<String>, String, DateTime<Utc>)>> {
+        let posts = sqlx::query_as::<_, (i64, Option<String>, String, DateTime<Utc>)>(
+            "SELECT id, title,
+                    CASE WHEN btrim(coalesce(title, '')) = '' THEN body ELSE '' END,
+                    updated_at
              FROM posts_post
-             WHERE is_draft = false AND is_deleted = false AND visibility = ANY($1)
-                   AND (title ILIKE $2 OR body ILIKE $2)
-             ORDER BY created_at DESC
-             LIMIT $3 OFFSET $4"
+             WHERE is_draft = false AND is_deleted = false AND visibility = 'PUBLIC'
+             ORDER BY id
+             LIMIT $1 OFFSET $2"
         )
-        .bind(&visibilities)
-        .bind(&search_pattern)
         .bind(limit)
         .bind(offset)
         .fetch_all(pool)
@@ -122,11 +159,300 @@ impl Post {
         Ok(posts)
     }
 
+    pub async fn count_public(pool: &PgPool) -> Result<i64> {
+        let count = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM posts_post
+             WHERE is_draft = false AND is_deleted = false AND visibility = 'PUBLIC'"
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// Every post a media library item is attached to, including drafts and deleted posts.
+    pub async fn list_for_media(pool: &PgPool, library_item_id: i64) -> Result<Vec<Self>> {
+        let posts = sqlx::query_as::<_, Post>(
+            "SELECT DISTINCT p.id, p.title, p.body, p.location, p.author_id, p.visibility, p.is_draft,
+                    p.is_pinned, p.is_archived, p.is_deleted, p.category_id, p.source_platform,
+                    p.like_count, p.comment_count, p.share_count, p.created_at, p.updated_at
+             FROM posts_post p
+             JOIN posts_postmedia pm ON p.id = pm.post_id
+             WHERE pm.library_item_id = $1"
+        )
+        .bind(library_item_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(posts)
+    }
+
+    /// Whether the user is in this post's hand-picked `CUSTOM` audience.
+    pub async fn is_shared_with(pool: &PgPool, post_id: i64, user_id: i32) -> Result<bool> {
+        if !privacy::has_custom_audiences() {
+            return Ok(false);
+        }
+        let sql = format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE post_id = $1 AND user_id = $2)",
+            privacy::CUSTOM_VIEWERS_TABLE
+        );
+        let shared = sqlx::query_scalar::<_, bool>(&sql)
+        .bind(post_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(shared)
+    }
+
+    /// Plain-text start of the body, with Markdown markup stripped.
     pub fn preview(&self) -> String {
-        if self.body.len() > 280 {
-            format!("{}...", &self.body[..280])
-        } else {
-            self.body.clone()
+        markdown::preview(&self.body)
+    }
+
+    pub fn slug(&self) -> String {
+        slug(self.title.as_deref(), &self.body)
+    }
+
+    /// Canonical `/posts/:id/:slug/` path.
+    pub fn url(&self) -> String {
+        url(self.id, self.title.as_deref(), &self.body)
+    }
+}
+
+/// Longest slug, in characters; longer titles are cut at a word boundary.
+const SLUG_LENGTH: usize = 60;
+
+/// Path segments after `/posts/:id/` taken by other routes, so no slug may use them.
+const RESERVED_SLUGS: &[&str] = &["preview", "share"];
+
+/// Lowercase ASCII slug of the title, or of the start of the body for untitled posts.
+/// Only for readability: posts are looked up by id, and a wrong slug just redirects.
+pub fn slug(title: Option<&str>, body: &str) -> String {
+    let source = match title.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(title) => title.to_string(),
+        None => markdown::plain_text(body),
+    };
+
+    let mut slug = String::new();
+    let mut word = String::new();
+    for c in source.chars().chain([' ']) {
+        match fold(c) {
+            Some(letters) => word.push_str(letters),
+            None if word.is_empty() => {}
+            None => {
+                if !slug.is_empty() && slug.len() + 1 + word.len() > SLUG_LENGTH {
+                    break;
+                }
+                if !slug.is_empty() {
+                    slug.push('-');
+                }
+                slug.push_str(&word[..word.len().min(SLUG_LENGTH)]);
+                word.clear();
+            }
         }
     }
+
+    if slug.is_empty() {
+        "post".to_string()
+    } else if RESERVED_SLUGS.contains(&slug.as_str()) {
+        format!("{}-post", slug)
+    } else {
+        slug
+    }
+}
+
+/// `/posts/:id/:slug/` path of a post.
+pub fn url(id: i64, title: Option<&str>, body: &str) -> String {
+    format!("/posts/{}/{}/", id, slug(title, body))
+}
+
+/// ASCII letters for a slug character (accents dropped), or `None` for a word separator.
+fn fold(c: char) -> Option<&'static str> {
+    const ASCII: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+    let c = c.to_lowercase().next().unwrap_or(c);
+    let folded = match c {
+        '0'..='9' | 'a'..='z' => {
+            let i = ASCII.find(c).unwrap_or(0);
+            &ASCII[i..i + 1]
+        }
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => "a",
+        'ç' => "c",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'ñ' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => "o",
+        'ù' | 'ú' | 'û' | 'ü' => "u",
+        'ý' | 'ÿ' => "y",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        // Apostrophes join their word ("don't" -> "dont")
+        '\'' | '’' => "",
+        _ => return None,
+    };
+    Some(folded)
+}
+
+
+// SQL of the visibility-filtered queries, kept apart so the tests can check the filter.
+
+fn list_public_sql() -> String {
+    format!(
+        "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
+                is_archived, is_deleted, category_id, source_platform, like_count,
+                comment_count, share_count, created_at, updated_at
+         FROM posts_post
+         WHERE is_draft = false AND is_deleted = false AND {}
+         ORDER BY is_pinned DESC, created_at DESC
+         LIMIT $3 OFFSET $4",
+        post_filter_sql("posts_post.", 1, 2)
+    )
+}
+
+fn list_by_category_sql() -> String {
+    format!(
+        "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
+                is_archived, is_deleted, category_id, source_platform, like_count,
+                comment_count, share_count, created_at, updated_at
+         FROM posts_post
+         WHERE is_draft = false AND is_deleted = false AND {}
+               AND category_id IN (
+                   WITH RECURSIVE subtree AS (
+                       SELECT id FROM posts_category WHERE id = $1
+                       UNION
+                       SELECT c.id FROM posts_category c
+                       JOIN subtree s ON c.parent_id = s.id
+                       WHERE c.is_active = true
+                   )
+                   SELECT id FROM subtree
+               )
+         ORDER BY is_pinned DESC, created_at DESC
+         LIMIT $4 OFFSET $5",
+        post_filter_sql("posts_post.", 2, 3)
+    )
+}
+
+fn list_by_tag_sql() -> String {
+    format!(
+        "SELECT p.id, p.title, p.body, p.location, p.author_id, p.visibility, p.is_draft,
+                p.is_pinned, p.is_archived, p.is_deleted, p.category_id, p.source_platform,
+                p.like_count, p.comment_count, p.share_count, p.created_at, p.updated_at
+         FROM posts_post p
+         JOIN posts_post_tags pt ON p.id = pt.post_id
+         WHERE p.is_draft = false AND p.is_deleted = false AND pt.tag_id = $1 AND {}
+         ORDER BY p.is_pinned DESC, p.created_at DESC
+         LIMIT $4 OFFSET $5",
+        post_filter_sql("p.", 2, 3)
+    )
+}
+
+fn list_for_page_sql() -> String {
+    format!(
+        "SELECT id, title, body, location, author_id, visibility, is_draft, is_pinned,
+                is_archived, is_deleted, category_id, source_platform, like_count,
+                comment_count, share_count, created_at, updated_at
+         FROM posts_post
+         WHERE is_draft = false AND is_deleted = false AND {}
+               AND ($1::bigint IS NULL OR category_id IN (
+                   WITH RECURSIVE subtree AS (
+                       SELECT id FROM posts_category WHERE id = $1
+                       UNION
+                       SELECT c.id FROM posts_category c
+                       JOIN subtree s ON c.parent_id = s.id
+                       WHERE c.is_active = true
+                   )
+                   SELECT id FROM subtree
+               ))
+               AND ($2::bigint IS NULL OR EXISTS (
+                   SELECT 1 FROM posts_post_tags pt WHERE pt.post_id = posts_post.id AND pt.tag_id = $2
+               ))
+         ORDER BY is_pinned DESC, created_at DESC
+         LIMIT $5 OFFSET $6",
+        post_filter_sql("posts_post.", 3, 4)
+    )
+}
+
+fn count_by_category_sql() -> String {
+    format!(
+        "SELECT category_id, COUNT(*)
+         FROM posts_post
+         WHERE is_draft = false AND is_deleted = false AND category_id IS NOT NULL AND {}
+         GROUP BY category_id",
+        post_filter_sql("posts_post.", 1, 2)
+    )
+}
+
+fn count_by_tag_sql() -> String {
+    format!(
+        "SELECT pt.tag_id, COUNT(*)
+         FROM posts_post p
+         JOIN posts_post_tags pt ON p.id = pt.post_id
+         WHERE p.is_draft = false AND p.is_deleted = false AND {}
+         GROUP BY pt.tag_id",
+        post_filter_sql("p.", 1, 2)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The audience subquery must compare against the outer post, not `cv.id`.
+    fn assert_filter_uses_outer_post(sql: &str, outer: &str) {
+        assert!(sql.contains(&format!("cv.post_id = {}id ", outer)), "{}", sql);
+        assert!(sql.contains(&format!("{}visibility = ANY(", outer)), "{}", sql);
+    }
+
+    #[test]
+    fn visibility_filters_reference_the_outer_post() {
+        assert_filter_uses_outer_post(&list_public_sql(), "posts_post.");
+        assert_filter_uses_outer_post(&list_by_category_sql(), "posts_post.");
+        assert_filter_uses_outer_post(&list_by_tag_sql(), "p.");
+        assert_filter_uses_outer_post(&list_for_page_sql(), "posts_post.");
+        assert_filter_uses_outer_post(&count_by_category_sql(), "posts_post.");
+        assert_filter_uses_outer_post(&count_by_tag_sql(), "p.");
+    }
+
+    #[test]
+    fn slug_from_title() {
+        assert_eq!(slug(Some("Night dive at Palancar Reef!"), ""), "night-dive-at-palancar-reef");
+        assert_eq!(slug(Some("Don't  panic -- 2024"), ""), "dont-panic-2024");
+    }
+
+    #[test]
+    fn accents_are_folded() {
+        assert_eq!(slug(Some("Día de Muertos en Señor Nuño's café"), ""), "dia-de-muertos-en-senor-nunos-cafe");
+        assert_eq!(slug(Some("Straße, Æsir, Œuvre"), ""), "strasse-aesir-oeuvre");
+    }
+
+    #[test]
+    fn untitled_posts_use_the_body() {
+        assert_eq!(slug(None, "**Buceo** en [Cozumel](https://example.com)"), "buceo-en-cozumel");
+        assert_eq!(slug(Some("   "), "Just the body"), "just-the-body");
+        // Nothing sluggable at all
+        assert_eq!(slug(None, "🐢 🐢"), "post");
+        assert_eq!(slug(Some(""), ""), "post");
+    }
+
+    #[test]
+    fn reserved_slugs_are_avoided() {
+        assert_eq!(slug(Some("Preview"), ""), "preview-post");
+        assert_eq!(slug(Some("Share!"), ""), "share-post");
+        assert_eq!(slug(Some("Preview day"), ""), "preview-day");
+    }
+
+    #[test]
+    fn long_titles_are_cut_at_a_word() {
+        let title = "A very long title that keeps going and going well past sixty characters in total";
+        let slug = slug(Some(title), "");
+        assert_eq!(slug, "a-very-long-title-that-keeps-going-and-going-well-past-sixty");
+        assert!(slug.len() <= SLUG_LENGTH);
+
+        // A single overlong word is cut mid-word
+        let word = "x".repeat(100);
+        assert_eq!(super::slug(Some(&word), "").len(), SLUG_LENGTH);
+    }
+
+    #[test]
+    fn url_has_id_and_slug() {
+        assert_eq!(url(42, Some("Hello, World"), ""), "/posts/42/hello-world/");
+    }
 }
@@ -0,0 +1,196 @@
+use chrono::NaiveDate;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tokio::sync::OnceCell;
+
+use crate::error::Result;
+use crate::models::Post;
+use crate::privacy::Viewer;
+
+/// A search result: the matching post plus a highlighted excerpt of its body.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SearchHit {
+    #[sqlx(flatten)]
+    pub post: Post,
+    /// `None` when the search had no free-text terms to highlight
+    pub headline: Option<String>,
+}
+
+/// A parsed search box query: free text plus `key:value` operator filters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchFilters {
+    pub text: String,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+    pub location: Option<String>,
+    pub before: Option<NaiveDate>,
+    pub after: Option<NaiveDate>,
+    pub platform: Option<String>,
+}
+
+impl SearchFilters {
+    pub fn is_empty(&self) -> bool {
+        *self == SearchFilters::default()
+    }
+}
+
+/// Control characters marking matched terms in `SearchHit::headline`; unlike HTML tags
+/// they survive escaping the excerpt, so highlighting can be applied safely afterwards.
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_STOP: char = '\u{3}';
+const HEADLINE_OPTIONS: &str =
+    "StartSel=\u{2}, StopSel=\u{3}, MaxWords=35, MinWords=15, MaxFragments=2, FragmentDelimiter=\" … \"";
+
+/// Weighted title + body document; a GIN index on this exact expression (see README) lets
+/// Postgres use it, and without one searches still work through a sequential scan.
+const SEARCH_VECTOR_EXPRESSION: &str =
+    "(setweight(to_tsvector('english', coalesce(p.title, '')), 'A') || setweight(to_tsvector('english', p.body), 'B'))";
+
+static HAS_SEARCH_VECTOR_COLUMN: OnceCell<bool> = OnceCell::const_new();
+
+impl Post {
+    /// Full-text search ranked by `ts_rank`, with title matches weighted above body matches.
+    ///
+    /// Free text goes through `websearch_to_tsquery`, so `"quoted phrases"`, `or` and
+    /// `-exclusions` work as on a web search engine; operator filters narrow the results.
+    /// Each hit carries a `ts_headline` excerpt of the body with matches wrapped in
+    /// `HIGHLIGHT_START`/`HIGHLIGHT_STOP`. Filter-only searches are ordered newest first.
+    pub async fn search(
+        pool: &PgPool,
+        filters: &SearchFilters,
+        viewer: &Viewer<'_>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SearchHit>> {
+        let vector = search_vector_sql(pool).await?;
+        let has_text = !filters.text.trim().is_empty();
+
+        let mut qb = QueryBuilder::<Postgres>::new(
+            "SELECT p.id, p.title, p.body, p.location, p.author_id, p.visibility, p.is_draft,
+                    p.is_pinned, p.is_archived, p.is_deleted, p.category_id, p.source_platform,
+                    p.like_count, p.comment_count, p.share_count, p.created_at, p.updated_at, ",
+        );
+        if has_text {
+            qb.push("ts_headline('english', p.body, query, ")
+                .push_bind(HEADLINE_OPTIONS)
+                .push(") AS headline FROM posts_post p, websearch_to_tsquery('english', ")
+                .push_bind(filters.text.trim())
+                .push(") query");
+        } else {
+            qb.push("NULL::text AS headline FROM posts_post p");
+        }
+        push_conditions(&mut qb, filters, viewer, vector, has_text);
+
+        if has_text {
+            qb.push(format!(" ORDER BY ts_rank({vector}, query) DESC, p.created_at DESC"));
+        } else {
+            qb.push(" ORDER BY p.created_at DESC");
+        }
+        qb.push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+
+        let hits = qb.build_query_as::<SearchHit>().fetch_all(pool).await?;
+        Ok(hits)
+    }
+
+    /// Total number of posts `search` would return without paging.
+    pub async fn search_count(pool: &PgPool, filters: &SearchFilters, viewer: &Viewer<'_>) -> Result<i64> {
+        let vector = search_vector_sql(pool).await?;
+        let has_text = !filters.text.trim().is_empty();
+
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM posts_post p");
+        if has_text {
+            qb.push(", websearch_to_tsquery('english', ")
+                .push_bind(filters.text.trim())
+                .push(") query");
+        }
+        push_conditions(&mut qb, filters, viewer, vector, has_text);
+
+        let count = qb.build_query_scalar::<i64>().fetch_one(pool).await?;
+        Ok(count)
+    }
+}
+
+/// `WHERE` clause shared by search queries: visibility, full-text match and operators.
+fn push_conditions(
+    qb: &mut QueryBuilder<'_, Postgres>,
+    filters: &SearchFilters,
+    viewer: &Viewer<'_>,
+    vector: &str,
+    has_text: bool,
+) {
+    qb.push(" WHERE p.is_draft = false AND p.is_deleted = false AND ");
+    viewer.push_post_filter(qb, "p.");
+
+    if has_text {
+        qb.push(format!(" AND {vector} @@ query"));
+    }
+    for tag in &filters.tags {
+        qb.push(
+            " AND EXISTS (SELECT 1 FROM posts_post_tags pt JOIN posts_tag t ON t.id = pt.tag_id
+                          WHERE pt.post_id = p.id AND t.is_active = true AND (t.slug = ",
+        )
+        .push_bind(tag.clone())
+        .push(" OR lower(t.name) = lower(")
+        .push_bind(tag.clone())
+        .push(")))");
+    }
+    for category in &filters.categories {
+        qb.push(
+            " AND p.category_id IN (
+                  WITH RECURSIVE subtree AS (
+                      SELECT c.id FROM posts_category c
+                      WHERE c.is_active = true AND (c.slug = ",
+        )
+        .push_bind(category.clone())
+        .push(" OR lower(c.name) = lower(")
+        .push_bind(category.clone())
+        .push(
+            "))
+                      UNION
+                      SELECT c.id FROM posts_category c
+                      JOIN subtree s ON c.parent_id = s.id
+                      WHERE c.is_active = true
+                  )
+                  SELECT id FROM subtree
+              )",
+        );
+    }
+    if let Some(ref location) = filters.location {
+        qb.push(" AND p.location ILIKE ")
+            .push_bind(format!("%{}%", escape_like(location)));
+    }
+    if let Some(before) = filters.before {
+        qb.push(" AND p.created_at < ").push_bind(before);
+    }
+    if let Some(after) = filters.after {
+        qb.push(" AND p.created_at >= ").push_bind(after);
+    }
+    if let Some(ref platform) = filters.platform {
+        qb.push(" AND upper(p.source_platform) = upper(")
+            .push_bind(platform.clone())
+            .push(")");
+    }
+}
+
+/// Escapes `LIKE` wildcards so user input only matches literally.
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// The tsvector to search against: Django's stored `search_vector` column when the
+/// database has one, otherwise the weighted vector computed from title and body.
+async fn search_vector_sql(pool: &PgPool) -> Result<&'static str> {
+    let has_column = HAS_SEARCH_VECTOR_COLUMN
+        .get_or_try_init(|| async {
+            sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (
+                     SELECT 1 FROM information_schema.columns
+                     WHERE table_schema = current_schema()
+                           AND table_name = 'posts_post' AND column_name = 'search_vector'
+                 )"
+            )
+            .fetch_one(pool)
+            .await
+        })
+        .await?;
+    Ok(if *has_column { "p.search_vector" } else { SEARCH_VECTOR_EXPRESSION })
+}
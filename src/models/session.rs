@@ -0,0 +1,152 @@
+use std::io::Read;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use flate2::read::ZlibDecoder;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::error::Result;
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DjangoSession {
+    pub session_data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionPayload {
+    #[serde(rename = "_auth_user_id")]
+    auth_user_id: Option<serde_json::Value>,
+    /// HMAC of the user's password hash; changes (logging the session out) with the password
+    #[serde(rename = "_auth_user_hash")]
+    auth_user_hash: Option<String>,
+}
+
+/// The `auth_user` fields needed to decide whether a session is still valid.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DjangoUser {
+    pub id: i32,
+    pub password: String,
+    pub is_active: bool,
+}
+
+impl DjangoUser {
+    pub async fn find_by_id(pool: &PgPool, id: i32) -> Result<Option<Self>> {
+        let user = sqlx::query_as::<_, DjangoUser>(
+            "SELECT id, password, is_active FROM auth_user WHERE id = $1"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        Ok(user)
+    }
+}
+
+impl DjangoSession {
+    pub async fn find_active(pool: &PgPool, session_key: &str) -> Result<Option<Self>> {
+        let session = sqlx::query_as::<_, DjangoSession>(
+            "SELECT session_data
+             FROM django_session WHERE session_key = $1 AND expire_date > NOW()"
+        )
+        .bind(session_key)
+        .fetch_optional(pool)
+        .await?;
+        Ok(session)
+    }
+
+    /// Returns the logged-in Django user id stored in the session, if any.
+    ///
+    /// Django stores sessions as `signing.dumps()` output: `payload:timestamp:signature`,
+    /// where the payload is URL-safe base64 JSON, zlib-compressed when prefixed with `.`.
+    /// The row comes from our own database, so the signature is not re-checked here.
+    pub fn user_id(&self) -> Option<i32> {
+        match self.payload()?.auth_user_id? {
+            serde_json::Value::String(s) => s.parse().ok(),
+            serde_json::Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+            _ => None,
+        }
+    }
+
+    /// The session auth hash Django stored at login, to check against the current password.
+    pub fn auth_user_hash(&self) -> Option<String> {
+        self.payload()?.auth_user_hash
+    }
+
+    fn payload(&self) -> Option<SessionPayload> {
+        let payload = self.session_data.split(':').next()?;
+        let (compressed, encoded) = match payload.strip_prefix('.') {
+            Some(rest) => (true, rest),
+            None => (false, payload),
+        };
+
+        let raw = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+        let json = if compressed {
+            let mut decoded = Vec::new();
+            ZlibDecoder::new(raw.as_slice()).read_to_end(&mut decoded).ok()?;
+            decoded
+        } else {
+            raw
+        };
+
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    /// Session data the way Django's `signing.dumps()` writes it (signature not checked).
+    fn session(json: &str, compress: bool) -> DjangoSession {
+        let payload = if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(json.as_bytes()).unwrap();
+            format!(".{}", URL_SAFE_NO_PAD.encode(encoder.finish().unwrap()))
+        } else {
+            URL_SAFE_NO_PAD.encode(json)
+        };
+        DjangoSession { session_data: format!("{}:1rXyZa:c2lnbmF0dXJl", payload) }
+    }
+
+    #[test]
+    fn plain_and_compressed_payloads() {
+        let json = r#"{"_auth_user_id":"42","_auth_user_backend":"django.contrib.auth.backends.ModelBackend"}"#;
+        assert_eq!(session(json, false).user_id(), Some(42));
+        assert_eq!(session(json, true).user_id(), Some(42));
+    }
+
+    #[test]
+    fn string_and_number_user_ids() {
+        assert_eq!(session(r#"{"_auth_user_id":"7"}"#, false).user_id(), Some(7));
+        assert_eq!(session(r#"{"_auth_user_id":7}"#, false).user_id(), Some(7));
+        assert_eq!(session(r#"{"_auth_user_id":"seven"}"#, false).user_id(), None);
+        assert_eq!(session(r#"{"_auth_user_id":99999999999}"#, false).user_id(), None);
+        assert_eq!(session(r#"{"_auth_user_id":null}"#, false).user_id(), None);
+    }
+
+    #[test]
+    fn auth_user_hash_is_read() {
+        let session = session(r#"{"_auth_user_id":"7","_auth_user_hash":"abc123"}"#, true);
+        assert_eq!(session.auth_user_hash().as_deref(), Some("abc123"));
+        assert_eq!(self::session(r#"{"_auth_user_id":"7"}"#, false).auth_user_hash(), None);
+    }
+
+    #[test]
+    fn anonymous_sessions_have_no_user() {
+        assert_eq!(session(r#"{"django_language":"es"}"#, true).user_id(), None);
+    }
+
+    #[test]
+    fn garbage_is_ignored() {
+        for data in ["", ":", "not base64!:x:y", ".bm90IHpsaWI:x:y", "bm90IGpzb24:x:y"] {
+            let session = DjangoSession { session_data: data.to_string() };
+            assert_eq!(session.user_id(), None, "{:?}", data);
+        }
+        assert_eq!(session("[1, 2]", false).user_id(), None);
+    }
+}
@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::db::TableProbe;
+use crate::error::{AppError, Result};
+
+/// Denylist entry for a share link revoked before its expiry.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RevokedShareLink {
+    pub token_id: String,
+    pub post_id: i64,
+    pub revoked_at: DateTime<Utc>,
+}
+
+/// `posts_revokedsharelink` is not created by Django (see README). It is probed again while
+/// missing, so links revoked right after creating it are refused without a restart.
+static TABLE: TableProbe = TableProbe::new("posts_revokedsharelink");
+
+impl RevokedShareLink {
+    /// Without the denylist table nothing can have been revoked.
+    pub async fn is_revoked(pool: &PgPool, token_id: &str) -> Result<bool> {
+        if !TABLE.exists(pool).await? {
+            return Ok(false);
+        }
+        let revoked = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM posts_revokedsharelink WHERE token_id = $1)"
+        )
+        .bind(token_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(revoked)
+    }
+
+    pub async fn create(pool: &PgPool, token_id: &str, post_id: i64) -> Result<Self> {
+        if !TABLE.exists(pool).await? {
+            return Err(AppError::Internal(
+                "posts_revokedsharelink does not exist, so share links can't be revoked".to_string(),
+            ));
+        }
+        let revoked = sqlx::query_as::<_, RevokedShareLink>(
+            "INSERT INTO posts_revokedsharelink (token_id, post_id, revoked_at)
+             VALUES ($1, $2, NOW())
+             ON CONFLICT (token_id) DO UPDATE SET revoked_at = posts_revokedsharelink.revoked_at
+             RETURNING token_id, post_id, revoked_at"
+        )
+        .bind(token_id)
+        .bind(post_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(revoked)
+    }
+}
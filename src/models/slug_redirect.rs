@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::db::TableProbe;
+use crate::error::Result;
+
+/// Old slug left behind when a tag or category is renamed in Django, pointing at the
+/// object that now owns the listing so old links can be redirected.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SlugRedirect {
+    pub model: String,
+    pub old_slug: String,
+    pub object_id: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// `posts_slugredirect` is not created by Django (see README).
+static TABLE: TableProbe = TableProbe::new("posts_slugredirect");
+
+impl SlugRedirect {
+    pub const TAG: &'static str = "tag";
+    pub const CATEGORY: &'static str = "category";
+
+    /// Most recent redirect for `old_slug` of the given model (`TAG` or `CATEGORY`), or
+    /// `None` when the database has no redirect table.
+    pub async fn find(pool: &PgPool, model: &str, old_slug: &str) -> Result<Option<Self>> {
+        if !TABLE.exists(pool).await? {
+            return Ok(None);
+        }
+        let redirect = sqlx::query_as::<_, SlugRedirect>(
+            "SELECT model, old_slug, object_id, created_at
+             FROM posts_slugredirect WHERE model = $1 AND old_slug = $2
+             ORDER BY created_at DESC
+             LIMIT 1"
+        )
+        .bind(model)
+        .bind(old_slug)
+        .fetch_optional(pool)
+        .await?;
+        Ok(redirect)
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 
 use crate::error::Result;
+use crate::privacy::{post_filter_sql, Viewer};
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Tag {
@@ -61,4 +62,64 @@ impl Tag {
         .await?;
         Ok(tags)
     }
+
+    /// Slugs of active tags on `PUBLIC` posts, with the latest `updated_at` among those posts.
+    pub async fn list_public_lastmod(pool: &PgPool) -> Result<Vec<(String, DateTime<Utc>)>> {
+        let tags = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+            "SELECT t.slug, MAX(p.updated_at)
+             FROM posts_tag t
+             JOIN posts_post_tags pt ON t.id = pt.tag_id
+             JOIN posts_post p ON p.id = pt.post_id
+             WHERE t.is_active = true
+                   AND p.is_draft = false AND p.is_deleted = false AND p.visibility = 'PUBLIC'
+             GROUP BY t.slug
+             ORDER BY t.slug"
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(tags)
+    }
+
+    /// Tags most often used alongside `tag_id` on posts the viewer can see.
+    pub async fn list_related(pool: &PgPool, tag_id: i64, viewer: &Viewer<'_>, limit: i64) -> Result<Vec<Self>> {
+        let sql = list_related_sql();
+        let tags = sqlx::query_as::<_, Tag>(&sql)
+        .bind(tag_id)
+        .bind(viewer.allowed_visibilities())
+        .bind(viewer.user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+        Ok(tags)
+    }
+}
+
+// SQL of the visibility-filtered query, kept apart so the tests can check the filter.
+
+fn list_related_sql() -> String {
+    format!(
+        "SELECT t.id, t.name, t.slug, t.is_active, t.description, t.created_at
+         FROM posts_tag t
+         JOIN posts_post_tags pt ON t.id = pt.tag_id
+         JOIN posts_post_tags base ON base.post_id = pt.post_id AND base.tag_id = $1
+         JOIN posts_post p ON p.id = pt.post_id
+         WHERE t.id <> $1 AND t.is_active = true
+               AND p.is_draft = false AND p.is_deleted = false AND {}
+         GROUP BY t.id
+         ORDER BY COUNT(*) DESC, t.name
+         LIMIT $4",
+        post_filter_sql("p.", 2, 3)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn related_tags_filter_references_the_outer_post() {
+        let sql = list_related_sql();
+        assert!(sql.contains("cv.post_id = p.id "), "{}", sql);
+        assert!(sql.contains("p.visibility = ANY("), "{}", sql);
+    }
 }
@@ -1,3 +1,29 @@
+use std::sync::OnceLock;
+
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::db;
+use crate::error::{AppError, Result};
+use crate::models::{MediaLibrary, Page, Post, Profile};
+
+/// Django's table for the `custom_viewers` many-to-many of `Post`: the hand-picked audience
+/// of a `CUSTOM` post.
+pub const CUSTOM_VIEWERS_TABLE: &str = "posts_post_custom_viewers";
+
+/// Set at startup by `detect_custom_audiences`; assumed present until then.
+static HAS_CUSTOM_VIEWERS: OnceLock<bool> = OnceLock::new();
+
+/// Checks once whether `CUSTOM_VIEWERS_TABLE` exists. Without it, `CUSTOM` posts are
+/// only visible to admins instead of every query failing.
+pub async fn detect_custom_audiences(pool: &PgPool) -> std::result::Result<bool, sqlx::Error> {
+    let exists = db::table_exists(pool, CUSTOM_VIEWERS_TABLE).await?;
+    Ok(*HAS_CUSTOM_VIEWERS.get_or_init(|| exists))
+}
+
+pub fn has_custom_audiences() -> bool {
+    HAS_CUSTOM_VIEWERS.get().copied().unwrap_or(true)
+}
+
 pub fn allowed_visibilities(user_tier: Option<&str>) -> Vec<&'static str> {
     match user_tier {
         None => vec!["PUBLIC"],
@@ -13,3 +39,273 @@ pub fn allowed_visibilities(user_tier: Option<&str>) -> Vec<&'static str> {
 pub fn can_view_visibility(user_tier: Option<&str>, visibility: &str) -> bool {
     allowed_visibilities(user_tier).contains(&visibility)
 }
+
+pub fn can_see_dates(user_tier: Option<&str>) -> bool {
+    matches!(user_tier, Some("REGISTERED" | "FRIEND" | "CLOSE_FRIEND" | "ADMIN"))
+}
+
+/// Whoever is requesting content: anonymous, or a Django user with a profile tier.
+///
+/// Tier rules come from `allowed_visibilities`; on top of that, a `CUSTOM` post is
+/// visible to exactly the users listed in its audience (`posts_post_custom_viewers`),
+/// and a valid share link opens up one post and its media.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Viewer<'a> {
+    pub user_id: Option<i32>,
+    pub tier: Option<&'a str>,
+    pub shared_post_id: Option<i64>,
+}
+
+impl<'a> Viewer<'a> {
+    pub fn new(profile: Option<&'a Profile>) -> Self {
+        Viewer {
+            user_id: profile.map(|p| p.user_id),
+            tier: profile.map(|p| p.tier.as_str()),
+            shared_post_id: None,
+        }
+    }
+
+    /// Grants access to a single post through a verified share link.
+    pub fn with_share(self, post_id: i64) -> Self {
+        Viewer { shared_post_id: Some(post_id), ..self }
+    }
+
+    pub fn allowed_visibilities(&self) -> Vec<&'static str> {
+        allowed_visibilities(self.tier)
+    }
+
+    pub fn can_see_dates(&self) -> bool {
+        can_see_dates(self.tier)
+    }
+
+    pub fn can_view_page(&self, page: &Page) -> bool {
+        can_view_visibility(self.tier, &page.visibility)
+    }
+
+    pub async fn can_view_post(&self, pool: &PgPool, post: &Post) -> Result<bool> {
+        if can_view_visibility(self.tier, &post.visibility) || self.shared_post_id == Some(post.id) {
+            return Ok(true);
+        }
+        match (post.visibility.as_str(), self.user_id) {
+            ("CUSTOM", Some(user_id)) => Post::is_shared_with(pool, post.id, user_id).await,
+            _ => Ok(false),
+        }
+    }
+
+    /// `can_view_post`, failing with the same 404 as a missing post so existence isn't leaked.
+    pub async fn require_post(&self, pool: &PgPool, post: &Post) -> Result<()> {
+        if self.can_view_post(pool, post).await? {
+            Ok(())
+        } else {
+            Err(AppError::NotFound("Post not found".to_string()))
+        }
+    }
+
+    /// Appends the `post_filter_sql` predicate to a dynamically built query.
+    pub fn push_post_filter(&self, qb: &mut QueryBuilder<'_, Postgres>, prefix: &str) {
+        let [before_visibilities, before_user_id, after] = post_filter_parts(prefix);
+        qb.push(before_visibilities)
+            .push_bind(self.allowed_visibilities())
+            .push(before_user_id)
+            .push_bind(self.user_id)
+            .push(after);
+    }
+
+    /// Media is viewable when it isn't attached to any post, or when at least one
+    /// published post using it is viewable. Admins also see media of drafts, so previews
+    /// show their images.
+    pub async fn can_view_media(&self, pool: &PgPool, media: &MediaLibrary) -> Result<bool> {
+        let posts = Post::list_for_media(pool, media.id).await?;
+        if posts.is_empty() {
+            return Ok(true);
+        }
+        let is_admin = self.tier == Some("ADMIN");
+        for post in posts.iter().filter(|p| !p.is_deleted && (!p.is_draft || is_admin)) {
+            if self.can_view_post(pool, post).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// SQL predicate restricting `posts_post` rows to those a viewer may see.
+///
+/// `prefix` is the table name or alias including the dot, and the two numbers are the
+/// placeholder positions bound to `Viewer::allowed_visibilities()` and `Viewer::user_id`.
+pub fn post_filter_sql(prefix: &str, visibilities_param: usize, user_id_param: usize) -> String {
+    let [before_visibilities, before_user_id, after] = post_filter_parts(prefix);
+    format!("{before_visibilities}${visibilities_param}{before_user_id}${user_id_param}{after}")
+}
+
+/// The visibility predicate, split where the allowed visibilities and the user id are
+/// bound. Both `post_filter_sql` and `Viewer::push_post_filter` are built from it.
+///
+/// The prefix is required: inside the audience subquery a bare `id` would resolve to
+/// `posts_post_custom_viewers.id` instead of the outer post.
+fn post_filter_parts(prefix: &str) -> [String; 3] {
+    filter_parts(prefix, has_custom_audiences())
+}
+
+fn filter_parts(prefix: &str, custom_audiences: bool) -> [String; 3] {
+    assert!(
+        prefix.len() > 1 && prefix.ends_with('.'),
+        "post filter needs a qualified table prefix like \"p.\", got {:?}",
+        prefix,
+    );
+    if !custom_audiences {
+        // The user id is still bound, so it has to appear in the query
+        return [
+            format!("({prefix}visibility = ANY("),
+            format!(") OR ({prefix}visibility = 'CUSTOM' AND false AND ("),
+            "::integer) IS NULL))".to_string(),
+        ];
+    }
+    [
+        format!("({prefix}visibility = ANY("),
+        format!(
+            ") OR ({prefix}visibility = 'CUSTOM' AND EXISTS (
+              SELECT 1 FROM {CUSTOM_VIEWERS_TABLE} cv
+              WHERE cv.post_id = {prefix}id AND cv.user_id = "
+        ),
+        ")))".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use chrono::Utc;
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+
+    const VISIBILITIES: [&str; 5] = ["PUBLIC", "FRIENDS", "CLOSE_FRIENDS", "PRIVATE", "CUSTOM"];
+
+    /// Visibilities each tier sees, in `VISIBILITIES` order.
+    const TIERS: [(Option<&str>, [bool; 5]); 7] = [
+        (None, [true, false, false, false, false]),
+        (Some("PUBLIC"), [true, false, false, false, false]),
+        (Some("REGISTERED"), [true, false, false, false, false]),
+        (Some("FRIEND"), [true, true, false, false, false]),
+        (Some("CLOSE_FRIEND"), [true, true, true, false, false]),
+        (Some("ADMIN"), [true, true, true, true, true]),
+        (Some("SUPERUSER"), [true, false, false, false, false]),
+    ];
+
+    fn page(visibility: &str) -> Page {
+        Page {
+            id: 1,
+            title: "About".to_string(),
+            slug: "about".to_string(),
+            body: None,
+            parent_id: None,
+            template: None,
+            show_in_nav: true,
+            display_order: 0,
+            show_posts_from_category_id: None,
+            show_posts_with_tag_id: None,
+            posts_per_page: 10,
+            is_published: true,
+            visibility: visibility.to_string(),
+            author_id: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn post(visibility: &str) -> Post {
+        Post {
+            id: 7,
+            title: Some("Hello".to_string()),
+            body: "Body".to_string(),
+            location: None,
+            author_id: 1,
+            visibility: visibility.to_string(),
+            is_draft: false,
+            is_pinned: false,
+            is_archived: false,
+            is_deleted: false,
+            category_id: None,
+            source_platform: "BLOG".to_string(),
+            like_count: 0,
+            comment_count: 0,
+            share_count: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Never connects; the checks below don't reach the database.
+    fn lazy_pool() -> PgPool {
+        PgPoolOptions::new().connect_lazy("postgres://localhost/unused").unwrap()
+    }
+
+    #[test]
+    fn each_tier_sees_exactly_its_visibilities() {
+        for (tier, expected) in TIERS {
+            for (visibility, allowed) in VISIBILITIES.into_iter().zip(expected) {
+                assert_eq!(can_view_visibility(tier, visibility), allowed, "{:?} viewing {}", tier, visibility);
+            }
+            assert!(!can_view_visibility(tier, "UNKNOWN"), "{:?} viewing an unknown visibility", tier);
+        }
+    }
+
+    #[test]
+    fn pages_follow_the_tier_rules() {
+        for (tier, expected) in TIERS {
+            let viewer = Viewer { user_id: tier.map(|_| 1), tier, shared_post_id: None };
+            for (visibility, allowed) in VISIBILITIES.into_iter().zip(expected) {
+                assert_eq!(viewer.can_view_page(&page(visibility)), allowed, "{:?} viewing {}", tier, visibility);
+            }
+        }
+    }
+
+    #[test]
+    fn query_builder_and_numbered_filters_match() {
+        let mut qb = QueryBuilder::<Postgres>::new("SELECT id FROM posts_post p WHERE ");
+        Viewer::new(None).push_post_filter(&mut qb, "p.");
+        assert_eq!(qb.sql(), format!("SELECT id FROM posts_post p WHERE {}", post_filter_sql("p.", 1, 2)));
+    }
+
+    #[test]
+    fn filter_without_audience_table_still_uses_both_binds() {
+        let [a, b, c] = filter_parts("p.", false);
+        let sql = format!("{a}$1{b}$2{c}");
+        assert_eq!(sql, "(p.visibility = ANY($1) OR (p.visibility = 'CUSTOM' AND false AND ($2::integer) IS NULL))");
+        assert!(!sql.contains(CUSTOM_VIEWERS_TABLE));
+    }
+
+    #[test]
+    #[should_panic(expected = "qualified table prefix")]
+    fn unqualified_prefix_is_refused() {
+        post_filter_sql("", 1, 2);
+    }
+
+    #[tokio::test]
+    async fn wrong_tier_is_not_found_rather_than_forbidden() {
+        let pool = lazy_pool();
+        let friend = Viewer { user_id: Some(1), tier: Some("FRIEND"), shared_post_id: None };
+        assert!(friend.require_post(&pool, &post("FRIENDS")).await.is_ok());
+
+        let error = friend.require_post(&pool, &post("CLOSE_FRIENDS")).await.unwrap_err();
+        assert!(matches!(error, AppError::NotFound(_)));
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+
+        // Anonymous viewers are never in a CUSTOM audience
+        let anonymous = Viewer::new(None);
+        let error = anonymous.require_post(&pool, &post("CUSTOM")).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn share_link_opens_only_its_post() {
+        let pool = lazy_pool();
+        let viewer = Viewer::new(None).with_share(7);
+        assert!(viewer.require_post(&pool, &post("PRIVATE")).await.is_ok());
+
+        let other = Post { id: 8, ..post("PRIVATE") };
+        assert!(matches!(viewer.require_post(&pool, &other).await, Err(AppError::NotFound(_))));
+    }
+}
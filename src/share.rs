@@ -0,0 +1,155 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::PgPool;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::models::RevokedShareLink;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A signed link granting access to a single post (and its media) until it expires.
+///
+/// Serialized as `post_id.expires_unix.token_id.signature`, where the signature is an
+/// HMAC-SHA256 of the first three fields keyed by `SHARE_LINK_SECRET`.
+#[derive(Debug, Clone)]
+pub struct ShareToken {
+    pub post_id: i64,
+    pub expires_at: DateTime<Utc>,
+    pub token_id: String,
+}
+
+impl ShareToken {
+    pub fn new(post_id: i64, ttl: Duration) -> Self {
+        ShareToken {
+            post_id,
+            expires_at: Utc::now() + ttl,
+            token_id: uuid::Uuid::new_v4().simple().to_string(),
+        }
+    }
+
+    fn payload(&self) -> String {
+        format!("{}.{}.{}", self.post_id, self.expires_at.timestamp(), self.token_id)
+    }
+
+    pub fn sign(&self, secret: &[u8]) -> String {
+        let payload = self.payload();
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Parses a token, returning it only if the signature matches and it hasn't expired.
+    pub fn verify(token: &str, secret: &[u8], now: DateTime<Utc>) -> Option<Self> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = HmacSha256::new_from_slice(secret).ok()?;
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+
+        let mut fields = payload.splitn(3, '.');
+        let post_id = fields.next()?.parse().ok()?;
+        let expires_at = DateTime::from_timestamp(fields.next()?.parse().ok()?, 0)?;
+        let token_id = fields.next()?.to_string();
+        if expires_at <= now {
+            return None;
+        }
+
+        Some(ShareToken { post_id, expires_at, token_id })
+    }
+}
+
+/// Resolves a `?share=` token into a live grant, checking signature, expiry and revocation.
+pub async fn resolve(pool: &PgPool, config: &Config, token: &str) -> Result<Option<ShareToken>> {
+    let Some(secret) = config.share_link_secret.as_deref() else {
+        return Ok(None);
+    };
+    let Some(share) = ShareToken::verify(token, secret.as_bytes(), Utc::now()) else {
+        return Ok(None);
+    };
+    if RevokedShareLink::is_revoked(pool, &share.token_id).await? {
+        return Ok(None);
+    }
+    Ok(Some(share))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn token() -> ShareToken {
+        ShareToken {
+            post_id: 42,
+            expires_at: DateTime::from_timestamp(1_900_000_000, 0).unwrap(),
+            token_id: "0123456789abcdef".to_string(),
+        }
+    }
+
+    fn before_expiry() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_800_000_000, 0).unwrap()
+    }
+
+    /// Replaces field `index` of a signed token, keeping the original signature.
+    fn tamper(signed: &str, index: usize, value: &str) -> String {
+        let mut fields: Vec<&str> = signed.split('.').collect();
+        fields[index] = value;
+        fields.join(".")
+    }
+
+    #[test]
+    fn round_trip() {
+        let signed = token().sign(SECRET);
+        let verified = ShareToken::verify(&signed, SECRET, before_expiry()).unwrap();
+        assert_eq!(verified.post_id, 42);
+        assert_eq!(verified.expires_at, token().expires_at);
+        assert_eq!(verified.token_id, "0123456789abcdef");
+    }
+
+    #[test]
+    fn tampered_fields_are_rejected() {
+        let signed = token().sign(SECRET);
+        for (index, value) in [(0, "43"), (1, "1999999999"), (2, "fedcba9876543210")] {
+            let tampered = tamper(&signed, index, value);
+            assert!(ShareToken::verify(&tampered, SECRET, before_expiry()).is_none(), "{}", tampered);
+        }
+    }
+
+    #[test]
+    fn bad_signatures_are_rejected() {
+        let signed = token().sign(SECRET);
+        let (payload, signature) = signed.rsplit_once('.').unwrap();
+        let truncated = format!("{}.{}", payload, &signature[..signature.len() - 4]);
+        let garbage = [
+            truncated,
+            format!("{}.not-base64!", payload),
+            format!("{}.", payload),
+            payload.to_string(),
+            "garbage".to_string(),
+            String::new(),
+        ];
+        for bad in garbage {
+            assert!(ShareToken::verify(&bad, SECRET, before_expiry()).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn wrong_secret_is_rejected() {
+        let signed = token().sign(b"another secret");
+        assert!(ShareToken::verify(&signed, SECRET, before_expiry()).is_none());
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let signed = token().sign(SECRET);
+        let expires_at = token().expires_at;
+        assert!(ShareToken::verify(&signed, SECRET, expires_at - Duration::seconds(1)).is_some());
+        assert!(ShareToken::verify(&signed, SECRET, expires_at).is_none());
+        assert!(ShareToken::verify(&signed, SECRET, expires_at + Duration::days(1)).is_none());
+    }
+}